@@ -0,0 +1,488 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	amm::{price_impact, Pool, SwapResult},
+	error::FeanorError,
+	math::{Decimal, Rounding},
+};
+
+// Osmosis concentrated liquidity pool
+//
+// the price is token1 per token0; swaps walk the initialized ticks from the
+// active one, charging the spread factor on every step like the chain does
+
+/// ticks between two powers of ten of the price
+const TICKS_PER_DECADE: i64 = 9_000_000;
+
+/// exponent of the price increment between ticks at a price of one
+const EXPONENT_AT_PRICE_ONE: i64 = -6;
+
+/// an initialized tick and the liquidity added when it is crossed left to right
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct Tick {
+	pub index: i64,
+	/// raw value of the osmomath `liquidity_net`, in units of 10^-18
+	pub liquidity_net: i128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct ConcentratedPool {
+	pub token0: String,
+	pub token1: String,
+	pub current_sqrt_price: Decimal,
+	pub current_tick: i64,
+	/// liquidity of the active range
+	pub liquidity: Decimal,
+	/// initialized ticks, sorted by index
+	pub ticks: Vec<Tick>,
+	pub spread_factor: Decimal,
+}
+
+// amounts moved by a single step inside one liquidity range
+
+struct Step {
+	sqrt_price_next: Decimal,
+	amount_in: Decimal,
+	amount_out: Decimal,
+	reached_target: bool,
+}
+
+impl ConcentratedPool {
+	pub fn new(
+		token0: String,
+		token1: String,
+		current_sqrt_price: Decimal,
+		current_tick: i64,
+		liquidity: Decimal,
+		mut ticks: Vec<Tick>,
+		spread_factor: Decimal,
+		) -> Result<Self, FeanorError> {
+		if token0 == token1 || current_sqrt_price.is_zero() || spread_factor >= Decimal::ONE {
+			return Err(FeanorError::InvalidPool);
+		}
+		ticks.sort_by_key(|tick| tick.index);
+		Ok(ConcentratedPool {
+			token0,
+			token1,
+			current_sqrt_price,
+			current_tick,
+			liquidity,
+			ticks,
+			spread_factor,
+		})
+	}
+
+	/// whether swapping `denom_in` for `denom_out` moves the price down (token0 in)
+	fn zero_for_one(&self, denom_in: &str, denom_out: &str) -> Result<bool, FeanorError> {
+		if denom_in == self.token0 && denom_out == self.token1 {
+			Ok(true)
+		} else if denom_in == self.token1 && denom_out == self.token0 {
+			Ok(false)
+		} else {
+			Err(FeanorError::InvalidPool)
+		}
+	}
+
+	/// walk the ticks until `amount` is used up, `amount` being the amount in
+	/// (fee included) when `exact_in` and the amount out otherwise
+	fn walk(&self, zero_for_one: bool, amount: u128, exact_in: bool) -> Result<SwapResult, FeanorError> {
+		let fee_complement = Decimal::ONE.checked_sub(self.spread_factor)?;
+
+		// next initialized tick in the direction of the swap
+		let mut next = if zero_for_one {
+			self.ticks.iter().rposition(|tick| tick.index <= self.current_tick)
+		} else {
+			self.ticks.iter().position(|tick| tick.index > self.current_tick)
+		};
+
+		let mut remaining = Decimal::from_int(amount)?;
+		let mut sqrt_price = self.current_sqrt_price;
+		let mut liquidity = self.liquidity;
+		let mut total_in = Decimal::ZERO;
+		let mut total_out = Decimal::ZERO;
+		let mut total_fee = Decimal::ZERO;
+
+		while !remaining.is_zero() {
+			let tick = next
+				.map(|i| &self.ticks[i])
+				.ok_or(FeanorError::InsufficientLiquidity)?;
+			let sqrt_price_target = tick_to_sqrt_price(tick.index)?;
+
+			let (step, fee) = if exact_in {
+				let remaining_less_fee = remaining.checked_mul(fee_complement)?;
+				let step = if zero_for_one {
+					step_in_zero_for_one(sqrt_price, sqrt_price_target, liquidity, remaining_less_fee)?
+				} else {
+					step_in_one_for_zero(sqrt_price, sqrt_price_target, liquidity, remaining_less_fee)?
+				};
+				// a partial step leaves nothing to swap, so the rest is all fee
+				let fee = if step.reached_target {
+					spread_fee(step.amount_in, self.spread_factor, fee_complement)?
+				} else {
+					remaining.checked_sub(step.amount_in)?
+				};
+				let consumed = step.amount_in.checked_add(fee)?.min(remaining);
+				remaining = remaining.checked_sub(consumed)?;
+				(step, fee)
+			} else {
+				let step = if zero_for_one {
+					step_out_zero_for_one(sqrt_price, sqrt_price_target, liquidity, remaining)?
+				} else {
+					step_out_one_for_zero(sqrt_price, sqrt_price_target, liquidity, remaining)?
+				};
+				let fee = spread_fee(step.amount_in, self.spread_factor, fee_complement)?;
+				remaining = remaining.checked_sub(step.amount_out.min(remaining))?;
+				(step, fee)
+			};
+
+			total_in = total_in.checked_add(step.amount_in)?.checked_add(fee)?;
+			total_out = total_out.checked_add(step.amount_out)?;
+			total_fee = total_fee.checked_add(fee)?;
+			sqrt_price = step.sqrt_price_next;
+
+			if !step.reached_target {
+				break;
+			}
+
+			// cross the tick, updating the active liquidity
+			let net = Decimal::raw(tick.liquidity_net.unsigned_abs());
+			let adds = (tick.liquidity_net >= 0) != zero_for_one;
+			liquidity = if adds {
+				liquidity.checked_add(net)?
+			} else {
+				liquidity.checked_sub(net).map_err(|_| FeanorError::InvalidPool)?
+			};
+			next = match next {
+				Some(i) if zero_for_one => i.checked_sub(1),
+				Some(i) if i + 1 < self.ticks.len() => Some(i + 1),
+				_ => None,
+			};
+		}
+
+		let (amount_in, amount_out) = if exact_in {
+			(amount, total_out.to_atomics(0, Rounding::Down)?)
+		} else {
+			(total_in.to_atomics(0, Rounding::Up)?, amount)
+		};
+		let fee_paid = total_fee.to_atomics(0, Rounding::Up)?.min(amount_in);
+		let spot_price_before = oriented_price(self.current_sqrt_price, zero_for_one)?;
+		let spot_price_after = oriented_price(sqrt_price, zero_for_one)?;
+
+		Ok(SwapResult {
+			amount_in,
+			amount_out,
+			fee_paid,
+			spot_price_before,
+			spot_price_after,
+			price_impact: price_impact(spot_price_before, amount_in - fee_paid, amount_out)?,
+		})
+	}
+}
+
+impl Pool for ConcentratedPool {
+	fn tokens(&self) -> Vec<&str> {
+		vec![self.token0.as_str(), self.token1.as_str()]
+	}
+
+	fn fee(&self) -> Decimal {
+		self.spread_factor
+	}
+
+	/// units of `denom_out` per unit of `denom_in` at the current sqrt price
+	fn spot_price(&self, denom_in: &str, denom_out: &str) -> Result<Decimal, FeanorError> {
+		oriented_price(self.current_sqrt_price, self.zero_for_one(denom_in, denom_out)?)
+	}
+
+	/// out amount for `amount_in` of `denom_in`, crossing as many ticks as needed
+	fn swap_exact_in(&self, denom_in: &str, denom_out: &str, amount_in: u128) -> Result<SwapResult, FeanorError> {
+		self.walk(self.zero_for_one(denom_in, denom_out)?, amount_in, true)
+	}
+
+	/// in amount of `denom_in`, spread factor included, needed to receive `amount_out`
+	fn swap_exact_out(&self, denom_in: &str, denom_out: &str, amount_out: u128) -> Result<SwapResult, FeanorError> {
+		self.walk(self.zero_for_one(denom_in, denom_out)?, amount_out, false)
+	}
+}
+
+/// price of the tick, token1 per token0, following the Osmosis geometric tick spacing
+pub fn tick_to_price(tick: i64) -> Result<Decimal, FeanorError> {
+	let (units, exponent) = tick_units(tick);
+	Decimal::from_int(units)?.shifted_by(exponent, Rounding::Down)
+}
+
+/// square root of the tick price, computed without the price itself so the
+/// highest ticks, whose price does not fit a `Decimal`, still work
+pub fn tick_to_sqrt_price(tick: i64) -> Result<Decimal, FeanorError> {
+	let (units, exponent) = tick_units(tick);
+	// sqrt(units * 10^exponent) with an even exponent left outside the root
+	let (units, exponent) = if exponent % 2 == 0 { (units, exponent) } else { (units * 10, exponent - 1) };
+	Decimal::from_int(units)?.sqrt().shifted_by(exponent / 2, Rounding::Down)
+}
+
+// the tick price as `units * 10^exponent`
+
+fn tick_units(tick: i64) -> (u128, i32) {
+	if tick == 0 {
+		return (1, 0);
+	}
+	let decades = tick / TICKS_PER_DECADE;
+	let mut exponent = EXPONENT_AT_PRICE_ONE + decades;
+	if tick < 0 {
+		exponent -= 1;
+	}
+	let additive_ticks = tick - decades * TICKS_PER_DECADE;
+	let units = 10i64.pow((decades - exponent) as u32) + additive_ticks;
+	(units as u128, exponent as i32)
+}
+
+/// units of the out token per unit of the in token at `sqrt_price`
+fn oriented_price(sqrt_price: Decimal, zero_for_one: bool) -> Result<Decimal, FeanorError> {
+	let price = sqrt_price.checked_mul(sqrt_price)?;
+	if zero_for_one {
+		Ok(price)
+	} else {
+		price.inv()
+	}
+}
+
+// exact in step, token0 in, price moving down towards `target`
+
+fn step_in_zero_for_one(
+	current: Decimal,
+	target: Decimal,
+	liquidity: Decimal,
+	remaining: Decimal,
+	) -> Result<Step, FeanorError> {
+	// amount0 = L * (current - target) / (current * target), too large to reach on overflow
+	let max_in = liquidity
+		.mul_rounded(current.checked_sub(target)?, Rounding::Up)
+		.and_then(|amount| amount.div_rounded(current, Rounding::Up))
+		.and_then(|amount| amount.div_rounded(target, Rounding::Up));
+
+	let (sqrt_price_next, amount_in, reached_target) = match max_in {
+		Ok(max_in) if remaining >= max_in => (target, max_in, true),
+		_ => {
+			// next = L * current / (L + remaining * current)
+			let denominator = liquidity.checked_add(remaining.checked_mul(current)?)?;
+			let next = liquidity
+				.mul_rounded(current, Rounding::Up)?
+				.div_rounded(denominator, Rounding::Up)?;
+			(next, remaining, false)
+		}
+	};
+
+	// amount1 = L * (current - next)
+	let amount_out = liquidity.checked_mul(current.checked_sub(sqrt_price_next)?)?;
+	Ok(Step { sqrt_price_next, amount_in, amount_out, reached_target })
+}
+
+// exact in step, token1 in, price moving up towards `target`
+
+fn step_in_one_for_zero(
+	current: Decimal,
+	target: Decimal,
+	liquidity: Decimal,
+	remaining: Decimal,
+	) -> Result<Step, FeanorError> {
+	// amount1 = L * (target - current), too large to reach on overflow
+	let max_in = liquidity.mul_rounded(target.checked_sub(current)?, Rounding::Up);
+
+	let (sqrt_price_next, amount_in, reached_target) = match max_in {
+		Ok(max_in) if remaining >= max_in => (target, max_in, true),
+		_ => {
+			// next = current + remaining / L
+			let next = current.checked_add(remaining.checked_div(liquidity)?)?;
+			(next, remaining, false)
+		}
+	};
+
+	// amount0 = L * (next - current) / (next * current)
+	let amount_out = liquidity
+		.checked_mul(sqrt_price_next.checked_sub(current)?)?
+		.checked_div(sqrt_price_next)?
+		.checked_div(current)?;
+	Ok(Step { sqrt_price_next, amount_in, amount_out, reached_target })
+}
+
+// exact out step, token1 out, price moving down towards `target`
+
+fn step_out_zero_for_one(
+	current: Decimal,
+	target: Decimal,
+	liquidity: Decimal,
+	remaining: Decimal,
+	) -> Result<Step, FeanorError> {
+	// amount1 = L * (current - target), too large to reach on overflow
+	let max_out = liquidity.checked_mul(current.checked_sub(target)?);
+
+	let (sqrt_price_next, amount_out, reached_target) = match max_out {
+		Ok(max_out) if remaining >= max_out => (target, max_out, true),
+		_ => {
+			// next = current - remaining / L, rounded so the trader pays more
+			let next = current.checked_sub(remaining.div_rounded(liquidity, Rounding::Up)?)?;
+			(next, remaining, false)
+		}
+	};
+
+	// amount0 = L * (current - next) / (current * next)
+	let amount_in = liquidity
+		.mul_rounded(current.checked_sub(sqrt_price_next)?, Rounding::Up)?
+		.div_rounded(current, Rounding::Up)?
+		.div_rounded(sqrt_price_next, Rounding::Up)?;
+	Ok(Step { sqrt_price_next, amount_in, amount_out, reached_target })
+}
+
+// exact out step, token0 out, price moving up towards `target`
+
+fn step_out_one_for_zero(
+	current: Decimal,
+	target: Decimal,
+	liquidity: Decimal,
+	remaining: Decimal,
+	) -> Result<Step, FeanorError> {
+	// amount0 = L * (target - current) / (current * target), too large to reach on overflow
+	let max_out = liquidity
+		.checked_mul(target.checked_sub(current)?)
+		.and_then(|amount| amount.checked_div(current))
+		.and_then(|amount| amount.checked_div(target));
+
+	let (sqrt_price_next, amount_out, reached_target) = match max_out {
+		Ok(max_out) if remaining >= max_out => (target, max_out, true),
+		_ => {
+			// next = L * current / (L - remaining * current)
+			let denominator = liquidity
+				.checked_sub(remaining.mul_rounded(current, Rounding::Up)?)
+				.map_err(|_| FeanorError::InsufficientLiquidity)?;
+			if denominator.is_zero() {
+				return Err(FeanorError::InsufficientLiquidity);
+			}
+			let next = liquidity
+				.mul_rounded(current, Rounding::Up)?
+				.div_rounded(denominator, Rounding::Up)?;
+			(next, remaining, false)
+		}
+	};
+
+	// amount1 = L * (next - current)
+	let amount_in = liquidity.mul_rounded(sqrt_price_next.checked_sub(current)?, Rounding::Up)?;
+	Ok(Step { sqrt_price_next, amount_in, amount_out, reached_target })
+}
+
+/// spread factor owed on `amount_in`, charged on top of it
+fn spread_fee(amount_in: Decimal, spread_factor: Decimal, fee_complement: Decimal) -> Result<Decimal, FeanorError> {
+	amount_in
+		.mul_rounded(spread_factor, Rounding::Up)?
+		.div_rounded(fee_complement, Rounding::Up)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn dec(value: &str) -> Decimal {
+		value.parse().unwrap()
+	}
+
+	#[test]
+	fn tick_to_sqrt_price_follows_the_geometric_spacing() {
+		let cases = [
+			(0, "1"),
+			(100, "1.000049998750062496"),
+			(-100, "0.999994999987499937"),
+			(9_000_000, "3.162277660168379331"),
+			(-9_000_000, "0.316227766016837933"),
+			(342_000_000, "10000000000000000000"),
+			(-108_000_000, "0.000001"),
+		];
+		for (tick, expected) in cases {
+			assert_eq!(tick_to_sqrt_price(tick), Ok(dec(expected)), "tick {}", tick);
+		}
+		assert_eq!(tick_to_price(100), Ok(dec("1.0001")));
+		assert_eq!(tick_to_price(-100), Ok(dec("0.99999")));
+		assert_eq!(tick_to_price(18_000_000), Ok(dec("100")));
+		assert_eq!(tick_to_price(-200), Ok(dec("0.99998")));
+		// the highest price does not fit a decimal but its square root does
+		assert_eq!(tick_to_price(342_000_000), Err(FeanorError::MathOverflow));
+	}
+
+	const WIDE: f64 = 1e12;
+	const NARROW: f64 = 4e12;
+	const FEE: f64 = 0.002;
+
+	// positions [-1000, 1000] and [-200, 200] around a price of one
+	fn pool() -> ConcentratedPool {
+		let raw = |liquidity: f64| (liquidity as i128) * 10i128.pow(18);
+		let ticks = vec![
+			Tick { index: 1000, liquidity_net: -raw(WIDE) },
+			Tick { index: -1000, liquidity_net: raw(WIDE) },
+			Tick { index: 200, liquidity_net: -raw(NARROW) },
+			Tick { index: -200, liquidity_net: raw(NARROW) },
+		];
+		let liquidity = Decimal::from_int((WIDE + NARROW) as u128).unwrap();
+		ConcentratedPool::new("uatom".into(), "uosmo".into(), Decimal::ONE, 0, liquidity, ticks, Decimal::bps(20)).unwrap()
+	}
+
+	// within one base unit of the closed form, amounts being rounded to integers
+	fn assert_close(actual: u128, expected: f64) {
+		assert!((actual as f64 - expected).abs() <= 1.0, "got {}, expected {}", actual, expected);
+	}
+
+	#[test]
+	fn swap_crosses_ticks_moving_the_price_down() {
+		let pool = pool();
+		let amount_in = 70_000_000u128;
+
+		// token0 in: both positions down to tick -200, then only the wide one
+		let net = amount_in as f64 * (1.0 - FEE);
+		let boundary = 0.99998f64.sqrt();
+		let first_in = (WIDE + NARROW) * (1.0 / boundary - 1.0);
+		let first_out = (WIDE + NARROW) * (1.0 - boundary);
+		let next = 1.0 / (1.0 / boundary + (net - first_in) / WIDE);
+		let expected = first_out + WIDE * (boundary - next);
+
+		let result = pool.swap_exact_in("uatom", "uosmo", amount_in).unwrap();
+		assert_close(result.amount_out, expected);
+		assert!(result.spot_price_after < tick_to_price(-200).unwrap());
+		assert!(result.spot_price_after > tick_to_price(-1000).unwrap());
+		assert_close(result.fee_paid, amount_in as f64 * FEE);
+
+		let back = pool.swap_exact_out("uatom", "uosmo", result.amount_out).unwrap();
+		assert!(back.amount_in <= amount_in && amount_in - back.amount_in <= 2, "{}", back.amount_in);
+	}
+
+	#[test]
+	fn swap_crosses_ticks_moving_the_price_up() {
+		let pool = pool();
+		let amount_in = 700_000_000u128;
+
+		// token1 in: both positions up to tick 200, then only the wide one
+		let net = amount_in as f64 * (1.0 - FEE);
+		let boundary = 1.0002f64.sqrt();
+		let first_in = (WIDE + NARROW) * (boundary - 1.0);
+		let first_out = (WIDE + NARROW) * (1.0 - 1.0 / boundary);
+		let next = boundary + (net - first_in) / WIDE;
+		let expected = first_out + WIDE * (1.0 / boundary - 1.0 / next);
+
+		let result = pool.swap_exact_in("uosmo", "uatom", amount_in).unwrap();
+		assert_close(result.amount_out, expected);
+		// prices are oriented as uatom per uosmo
+		assert!(result.spot_price_after < tick_to_price(200).unwrap().inv().unwrap());
+		assert!(result.spot_price_after > tick_to_price(1000).unwrap().inv().unwrap());
+
+		let back = pool.swap_exact_out("uosmo", "uatom", result.amount_out).unwrap();
+		assert!(back.amount_in <= amount_in && amount_in - back.amount_in <= 2, "{}", back.amount_in);
+	}
+
+	#[test]
+	fn swap_past_the_last_tick_fails() {
+		let pool = pool();
+		assert_eq!(
+			pool.swap_exact_in("uatom", "uosmo", 10u128.pow(12)),
+			Err(FeanorError::InsufficientLiquidity)
+		);
+		assert_eq!(
+			pool.swap_exact_out("uosmo", "uatom", 10u128.pow(12)),
+			Err(FeanorError::InsufficientLiquidity)
+		);
+	}
+}
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	amm::{
+		weighted::{in_given_out, out_given_in, spot_price, swap_result},
+		Pool, PoolAsset, SwapResult,
+	},
+	error::FeanorError,
+	math::{Decimal, Rounding},
+};
+
+// two asset x * y = k pool, the equal weight case of an Osmosis GAMM balancer pool
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct ConstantProduct {
+	pub assets: [PoolAsset; 2],
+	pub swap_fee: Decimal,
+}
+
+impl ConstantProduct {
+	pub fn new(assets: [PoolAsset; 2], swap_fee: Decimal) -> Result<Self, FeanorError> {
+		if assets[0].reserve == 0 || assets[1].reserve == 0 || swap_fee >= Decimal::ONE {
+			return Err(FeanorError::InvalidPool);
+		}
+		// weights are kept for the spot price but must be equal for x * y = k to hold
+		if assets[0].weight == 0 || assets[0].weight != assets[1].weight {
+			return Err(FeanorError::InvalidPool);
+		}
+		Ok(ConstantProduct { assets, swap_fee })
+	}
+
+	/// in and out side of a swap from `denom_in` to `denom_out`
+	fn sides(&self, denom_in: &str, denom_out: &str) -> Result<(&PoolAsset, &PoolAsset), FeanorError> {
+		match &self.assets {
+			[a, b] if a.denom == denom_in && b.denom == denom_out => Ok((a, b)),
+			[a, b] if b.denom == denom_in && a.denom == denom_out => Ok((b, a)),
+			_ => Err(FeanorError::InvalidPool),
+		}
+	}
+}
+
+impl Pool for ConstantProduct {
+	fn tokens(&self) -> Vec<&str> {
+		self.assets.iter().map(|asset| asset.denom.as_str()).collect()
+	}
+
+	fn fee(&self) -> Decimal {
+		self.swap_fee
+	}
+
+	fn spot_price(&self, denom_in: &str, denom_out: &str) -> Result<Decimal, FeanorError> {
+		let (asset_in, asset_out) = self.sides(denom_in, denom_out)?;
+		spot_price(asset_in.reserve, asset_in.weight, asset_out.reserve, asset_out.weight)
+	}
+
+	/// out = reserve_out * in / (reserve_in + in), rounded down like the chain does
+	fn swap_exact_in(&self, denom_in: &str, denom_out: &str, amount_in: u128) -> Result<SwapResult, FeanorError> {
+		let (asset_in, asset_out) = self.sides(denom_in, denom_out)?;
+		let amount_in_after_fee = Decimal::ONE
+			.checked_sub(self.swap_fee)?
+			.mul_int(amount_in, Rounding::Down)?;
+		let amount_out = out_given_in(asset_in, asset_out, amount_in_after_fee)?;
+		swap_result(asset_in, asset_out, amount_in, amount_in_after_fee, amount_out)
+	}
+
+	/// in = reserve_in * out / (reserve_out - out), rounded up
+	fn swap_exact_out(&self, denom_in: &str, denom_out: &str, amount_out: u128) -> Result<SwapResult, FeanorError> {
+		let (asset_in, asset_out) = self.sides(denom_in, denom_out)?;
+		let amount_in_after_fee = in_given_out(asset_in, asset_out, amount_out)?;
+		let amount_in = Decimal::ONE
+			.checked_sub(self.swap_fee)?
+			.div_int(amount_in_after_fee, Rounding::Up)?;
+		swap_result(asset_in, asset_out, amount_in, amount_in_after_fee, amount_out)
+	}
+}
@@ -0,0 +1,114 @@
+pub mod concentrated;
+pub mod constant_product;
+pub mod stableswap;
+pub mod weighted;
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	error::FeanorError,
+	math::{Decimal, Rounding},
+};
+
+pub use concentrated::ConcentratedPool;
+pub use constant_product::ConstantProduct;
+pub use stableswap::StableswapPool;
+pub use weighted::WeightedPool;
+
+// swap simulators for the pool kinds routes go through
+//
+// amounts are integer base units of the token (e.g. uosmo) and prices are
+// expressed as units of the out token per unit of the in token
+
+/// common interface of every pool model, so routes are evaluated the same way
+/// whatever kind of pool each step goes through
+pub trait Pool {
+	/// denoms traded by the pool
+	fn tokens(&self) -> Vec<&str>;
+
+	/// swap fee or spread factor charged on the amount in
+	fn fee(&self) -> Decimal;
+
+	/// units of `denom_out` per unit of `denom_in` for an infinitesimal trade, fee excluded
+	fn spot_price(&self, denom_in: &str, denom_out: &str) -> Result<Decimal, FeanorError>;
+
+	/// out amount received for `amount_in` of `denom_in`
+	fn swap_exact_in(&self, denom_in: &str, denom_out: &str, amount_in: u128) -> Result<SwapResult, FeanorError>;
+
+	/// in amount of `denom_in`, fee included, needed to receive `amount_out` of `denom_out`
+	fn swap_exact_out(&self, denom_in: &str, denom_out: &str, amount_out: u128) -> Result<SwapResult, FeanorError>;
+}
+
+/// pools keyed by their Osmosis pool id
+#[derive(Default)]
+pub struct PoolSet {
+	pools: HashMap<u64, Box<dyn Pool>>,
+}
+
+impl PoolSet {
+	pub fn new() -> Self {
+		PoolSet::default()
+	}
+
+	pub fn insert<P: Pool + 'static>(&mut self, pool_id: u64, pool: P) {
+		self.pools.insert(pool_id, Box::new(pool));
+	}
+
+	pub fn get(&self, pool_id: u64) -> Result<&dyn Pool, FeanorError> {
+		self.pools
+			.get(&pool_id)
+			.map(|pool| pool.as_ref())
+			.ok_or(FeanorError::PoolNotFound)
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = (u64, &dyn Pool)> {
+		self.pools.iter().map(|(id, pool)| (*id, pool.as_ref()))
+	}
+
+	pub fn len(&self) -> usize {
+		self.pools.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.pools.is_empty()
+	}
+}
+
+/// one side of a pool: its denom, reserve and weight
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct PoolAsset {
+	pub denom: String,
+	pub reserve: u128,
+	pub weight: u128,
+}
+
+/// outcome of a simulated swap
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct SwapResult {
+	pub amount_in: u128,
+	pub amount_out: u128,
+	/// part of `amount_in` taken as swap fee
+	pub fee_paid: u128,
+	pub spot_price_before: Decimal,
+	pub spot_price_after: Decimal,
+	/// relative gap between the spot price and the execution price, fee excluded
+	pub price_impact: Decimal,
+}
+
+/// relative gap between `spot_price` and the price obtained for `amount_out`
+pub(crate) fn price_impact(
+	spot_price: Decimal,
+	amount_in_after_fee: u128,
+	amount_out: u128,
+	) -> Result<Decimal, FeanorError> {
+	if amount_in_after_fee == 0 || spot_price.is_zero() {
+		return Ok(Decimal::ZERO);
+	}
+	let execution_price = Decimal::from_ratio(amount_out, amount_in_after_fee, Rounding::Down)?;
+	if execution_price >= spot_price {
+		return Ok(Decimal::ZERO);
+	}
+	spot_price.checked_sub(execution_price)?.checked_div(spot_price)
+}
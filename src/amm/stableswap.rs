@@ -0,0 +1,424 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	amm::{price_impact, Pool, SwapResult},
+	error::FeanorError,
+	math::{mul_div, Decimal, Rounding},
+};
+
+// Curve style stableswap pool
+//
+// the invariant A n^n sum(x) + D = A n^n D + D^(n+1) / (n^n prod(x)) is solved
+// with newton iterations on reserves brought to a common scale, so pairs like
+// USDC/USDT or milkTIA/TIA are priced close to 1:1 until the pool is unbalanced
+
+/// newton iterations before giving up on convergence
+const MAX_ITERATIONS: usize = 255;
+
+/// one side of a stableswap pool
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct StableAsset {
+	pub denom: String,
+	pub reserve: u128,
+	/// Osmosis scaling factor, the reserve is divided by it before pricing
+	pub scaling_factor: u128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct StableswapPool {
+	pub assets: Vec<StableAsset>,
+	/// amplification parameter A
+	pub amplification: u128,
+	pub swap_fee: Decimal,
+}
+
+impl StableswapPool {
+	pub fn new(assets: Vec<StableAsset>, amplification: u128, swap_fee: Decimal) -> Result<Self, FeanorError> {
+		if assets.len() < 2 || amplification == 0 || swap_fee >= Decimal::ONE {
+			return Err(FeanorError::InvalidPool);
+		}
+		if assets.iter().any(|asset| asset.reserve == 0 || asset.scaling_factor == 0) {
+			return Err(FeanorError::InvalidPool);
+		}
+		Ok(StableswapPool { assets, amplification, swap_fee })
+	}
+
+	fn index(&self, denom: &str) -> Result<usize, FeanorError> {
+		self.assets
+			.iter()
+			.position(|asset| asset.denom == denom)
+			.ok_or(FeanorError::InvalidPool)
+	}
+
+	fn indices(&self, denom_in: &str, denom_out: &str) -> Result<(usize, usize), FeanorError> {
+		let (i, j) = (self.index(denom_in)?, self.index(denom_out)?);
+		if i == j {
+			return Err(FeanorError::InvalidPool);
+		}
+		Ok((i, j))
+	}
+
+	/// multipliers bringing every reserve to the scale of the least common
+	/// multiple of the scaling factors, exact whether or not they divide each other
+	fn multipliers(&self) -> Result<Vec<u128>, FeanorError> {
+		let lcm = self.assets.iter().try_fold(1u128, |lcm, asset| {
+			(lcm / gcd(lcm, asset.scaling_factor))
+				.checked_mul(asset.scaling_factor)
+				.ok_or(FeanorError::MathOverflow)
+		})?;
+		Ok(self.assets.iter().map(|asset| lcm / asset.scaling_factor).collect())
+	}
+
+	fn scaled(reserves: &[u128], multipliers: &[u128]) -> Result<Vec<u128>, FeanorError> {
+		reserves
+			.iter()
+			.zip(multipliers)
+			.map(|(reserve, multiplier)| reserve.checked_mul(*multiplier).ok_or(FeanorError::MathOverflow))
+			.collect()
+	}
+
+	fn reserves(&self) -> Vec<u128> {
+		self.assets.iter().map(|asset| asset.reserve).collect()
+	}
+
+	/// A n^n
+	fn ann(&self) -> Result<u128, FeanorError> {
+		let n = self.assets.len() as u128;
+		n.checked_pow(n as u32)
+			.and_then(|nn| nn.checked_mul(self.amplification))
+			.ok_or(FeanorError::MathOverflow)
+	}
+
+	fn marginal_price(&self, reserves: &[u128], i: usize, j: usize) -> Result<Decimal, FeanorError> {
+		let multipliers = self.multipliers()?;
+		let xp = Self::scaled(reserves, &multipliers)?;
+		let ann = self.ann()?;
+		let d = invariant(&xp, ann)?;
+		let d_p = d_product(&xp, d)?;
+
+		// dy/dx = (Ann x_i + D_P) x_j / ((Ann x_j + D_P) x_i), on the common scale
+		let numerator = ann
+			.checked_mul(xp[i])
+			.and_then(|v| v.checked_add(d_p))
+			.ok_or(FeanorError::MathOverflow)?;
+		let denominator = ann
+			.checked_mul(xp[j])
+			.and_then(|v| v.checked_add(d_p))
+			.ok_or(FeanorError::MathOverflow)?;
+		let scaled_price = Decimal::from_ratio(numerator, denominator, Rounding::Down)?
+			.checked_mul(Decimal::from_ratio(xp[j], xp[i], Rounding::Down)?)?;
+
+		// back to base units of each token
+		scaled_price
+			.checked_mul(Decimal::from_ratio(multipliers[i], multipliers[j], Rounding::Down)?)
+	}
+
+	fn result(
+		&self,
+		reserves: &[u128],
+		i: usize,
+		j: usize,
+		amount_in: u128,
+		amount_in_after_fee: u128,
+		amount_out: u128,
+		) -> Result<SwapResult, FeanorError> {
+		let mut after = reserves.to_vec();
+		after[i] = after[i].checked_add(amount_in).ok_or(FeanorError::MathOverflow)?;
+		after[j] = after[j].checked_sub(amount_out).ok_or(FeanorError::InsufficientLiquidity)?;
+		if after[j] == 0 {
+			return Err(FeanorError::InsufficientLiquidity);
+		}
+
+		let spot_price_before = self.marginal_price(reserves, i, j)?;
+		let spot_price_after = self.marginal_price(&after, i, j)?;
+
+		Ok(SwapResult {
+			amount_in,
+			amount_out,
+			fee_paid: amount_in - amount_in_after_fee,
+			spot_price_before,
+			spot_price_after,
+			price_impact: price_impact(spot_price_before, amount_in_after_fee, amount_out)?,
+		})
+	}
+}
+
+impl Pool for StableswapPool {
+	fn tokens(&self) -> Vec<&str> {
+		self.assets.iter().map(|asset| asset.denom.as_str()).collect()
+	}
+
+	fn fee(&self) -> Decimal {
+		self.swap_fee
+	}
+
+	/// units of `denom_out` per unit of `denom_in` for an infinitesimal trade
+	fn spot_price(&self, denom_in: &str, denom_out: &str) -> Result<Decimal, FeanorError> {
+		let (i, j) = self.indices(denom_in, denom_out)?;
+		self.marginal_price(&self.reserves(), i, j)
+	}
+
+	/// out amount for `amount_in` of `denom_in`, fee taken on the input, rounded down
+	fn swap_exact_in(
+		&self,
+		denom_in: &str,
+		denom_out: &str,
+		amount_in: u128,
+		) -> Result<SwapResult, FeanorError> {
+		let (i, j) = self.indices(denom_in, denom_out)?;
+		let multipliers = self.multipliers()?;
+		let reserves = self.reserves();
+		let xp = Self::scaled(&reserves, &multipliers)?;
+		let ann = self.ann()?;
+		let d = invariant(&xp, ann)?;
+
+		let amount_in_after_fee = Decimal::ONE
+			.checked_sub(self.swap_fee)?
+			.mul_int(amount_in, Rounding::Down)?;
+		let x = amount_in_after_fee
+			.checked_mul(multipliers[i])
+			.and_then(|dx| dx.checked_add(xp[i]))
+			.ok_or(FeanorError::MathOverflow)?;
+		let y = solve_reserve(&xp, ann, d, i, x, j)?;
+
+		// one unit is kept back so rounding never favours the trader
+		let amount_out = xp[j].saturating_sub(y).saturating_sub(1) / multipliers[j];
+
+		self.result(&reserves, i, j, amount_in, amount_in_after_fee, amount_out)
+	}
+
+	/// in amount of `denom_in` needed to receive `amount_out` of `denom_out`, rounded up
+	fn swap_exact_out(
+		&self,
+		denom_in: &str,
+		denom_out: &str,
+		amount_out: u128,
+		) -> Result<SwapResult, FeanorError> {
+		let (i, j) = self.indices(denom_in, denom_out)?;
+		if amount_out >= self.assets[j].reserve {
+			return Err(FeanorError::InsufficientLiquidity);
+		}
+		let multipliers = self.multipliers()?;
+		let reserves = self.reserves();
+		let xp = Self::scaled(&reserves, &multipliers)?;
+		let ann = self.ann()?;
+		let d = invariant(&xp, ann)?;
+
+		let y = amount_out
+			.checked_mul(multipliers[j])
+			.and_then(|dy| xp[j].checked_sub(dy))
+			.ok_or(FeanorError::MathOverflow)?;
+		let x = solve_reserve(&xp, ann, d, j, y, i)?;
+		let scaled_in = x.saturating_sub(xp[i]).checked_add(1).ok_or(FeanorError::MathOverflow)?;
+		let amount_in_after_fee = scaled_in.div_ceil(multipliers[i]);
+		let amount_in = Decimal::ONE
+			.checked_sub(self.swap_fee)?
+			.div_int(amount_in_after_fee, Rounding::Up)?;
+
+		self.result(&reserves, i, j, amount_in, amount_in_after_fee, amount_out)
+	}
+}
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+	while b != 0 {
+		(a, b) = (b, a % b);
+	}
+	a
+}
+
+/// D^(n+1) / (n^n prod(x)), accumulated one reserve at a time to stay in range
+fn d_product(xp: &[u128], d: u128) -> Result<u128, FeanorError> {
+	let n = xp.len() as u128;
+	xp.iter().try_fold(d, |d_p, x| {
+		x.checked_mul(n)
+			.and_then(|divisor| mul_div(d_p, d, divisor, Rounding::Down))
+			.ok_or(FeanorError::MathOverflow)
+	})
+}
+
+/// the invariant D of the scaled reserves
+pub fn invariant(xp: &[u128], ann: u128) -> Result<u128, FeanorError> {
+	let n = xp.len() as u128;
+	let sum = xp
+		.iter()
+		.try_fold(0u128, |sum, x| sum.checked_add(*x))
+		.ok_or(FeanorError::MathOverflow)?;
+	if sum == 0 {
+		return Ok(0);
+	}
+
+	let mut d = sum;
+	for _ in 0..MAX_ITERATIONS {
+		let d_p = d_product(xp, d)?;
+		let previous = d;
+
+		// D = (Ann S + n D_P) D / ((Ann - 1) D + (n + 1) D_P)
+		let numerator = ann
+			.checked_mul(sum)
+			.and_then(|v| v.checked_add(d_p.checked_mul(n)?))
+			.ok_or(FeanorError::MathOverflow)?;
+		let denominator = ann
+			.checked_sub(1)
+			.and_then(|v| v.checked_mul(d))
+			.and_then(|v| v.checked_add(d_p.checked_mul(n + 1)?))
+			.ok_or(FeanorError::MathOverflow)?;
+		d = mul_div(numerator, d, denominator, Rounding::Down).ok_or(FeanorError::MathOverflow)?;
+
+		if d.abs_diff(previous) <= 1 {
+			return Ok(d);
+		}
+	}
+	Err(FeanorError::InvalidPool)
+}
+
+/// new scaled reserve of asset `j` once asset `i` is moved to `x`, keeping D constant
+pub fn solve_reserve(
+	xp: &[u128],
+	ann: u128,
+	d: u128,
+	i: usize,
+	x: u128,
+	j: usize,
+	) -> Result<u128, FeanorError> {
+	let n = xp.len() as u128;
+
+	// c = D^(n+1) / (n^n prod(x_k != j) Ann), b = sum(x_k != j) + D / Ann
+	//
+	// c is of the order of D^2, so only D^n / (n^(n-1) prod(x_k != j)) is kept
+	// and the last factor D / (n Ann) is applied together with the division by y
+	let mut c = d;
+	let mut sum = 0u128;
+	for (k, reserve) in xp.iter().enumerate() {
+		if k == j {
+			continue;
+		}
+		let reserve = if k == i { x } else { *reserve };
+		sum = sum.checked_add(reserve).ok_or(FeanorError::MathOverflow)?;
+		c = reserve
+			.checked_mul(n)
+			.and_then(|divisor| mul_div(c, d, divisor, Rounding::Down))
+			.ok_or(FeanorError::MathOverflow)?;
+	}
+	let ann_n = ann.checked_mul(n).ok_or(FeanorError::MathOverflow)?;
+	let b = sum.checked_add(d / ann).ok_or(FeanorError::MathOverflow)?;
+
+	// y = (y^2 + c) / (2y + b - D), with y^2 + c taken as y (y + c / y) to stay in range
+	let mut y = d;
+	for _ in 0..MAX_ITERATIONS {
+		let previous = y;
+		let c_over_y = ann_n
+			.checked_mul(y)
+			.and_then(|divisor| mul_div(c, d, divisor, Rounding::Down))
+			.ok_or(FeanorError::MathOverflow)?;
+		let denominator = y
+			.checked_mul(2)
+			.and_then(|v| v.checked_add(b))
+			.ok_or(FeanorError::MathOverflow)?
+			.checked_sub(d)
+			.ok_or(FeanorError::InvalidPool)?;
+		y = y
+			.checked_add(c_over_y)
+			.and_then(|v| mul_div(y, v, denominator, Rounding::Down))
+			.ok_or(FeanorError::MathOverflow)?;
+		if y.abs_diff(previous) <= 1 {
+			return Ok(y);
+		}
+	}
+	Err(FeanorError::InvalidPool)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn asset(denom: &str, reserve: u128, scaling_factor: u128) -> StableAsset {
+		StableAsset { denom: denom.into(), reserve, scaling_factor }
+	}
+
+	fn balanced() -> StableswapPool {
+		let assets = vec![asset("uusdc", 10u128.pow(12), 1), asset("uusdt", 10u128.pow(12), 1)];
+		StableswapPool::new(assets, 100, Decimal::bps(5)).unwrap()
+	}
+
+	#[test]
+	fn balanced_pool_swaps_close_to_one_for_one() {
+		let pool = balanced();
+		assert_eq!(pool.spot_price("uusdc", "uusdt"), Ok(Decimal::ONE));
+
+		// 10^6 in, minus 5 bps, with a slippage well below a unit per 10^6
+		let result = pool.swap_exact_in("uusdc", "uusdt", 1_000_000).unwrap();
+		assert_eq!(result.fee_paid, 500);
+		assert!((999_498..=999_500).contains(&result.amount_out), "{}", result.amount_out);
+
+		// a tenth of the pool still trades near par thanks to the amplification
+		let result = pool.swap_exact_in("uusdc", "uusdt", 10u128.pow(11)).unwrap();
+		let constant_product = 10u128.pow(12) * 99_950_000_000 / (10u128.pow(12) + 99_950_000_000);
+		assert!(result.amount_out > 99_000_000_000, "{}", result.amount_out);
+		assert!(result.amount_out > constant_product);
+		assert!(result.spot_price_after < Decimal::ONE);
+	}
+
+	#[test]
+	fn swap_exact_out_inverts_swap_exact_in() {
+		let assets = vec![
+			asset("uusdc", 4 * 10u128.pow(12), 1),
+			asset("uusdt", 10u128.pow(12), 1),
+			asset("udai", 2 * 10u128.pow(12), 1),
+		];
+		let pool = StableswapPool::new(assets, 50, Decimal::bps(30)).unwrap();
+		for (denom_in, denom_out) in [("uusdc", "uusdt"), ("uusdt", "udai"), ("udai", "uusdc")] {
+			for amount_in in [1_000u128, 1_000_000, 10u128.pow(11)] {
+				let forward = pool.swap_exact_in(denom_in, denom_out, amount_in).unwrap();
+				let back = pool.swap_exact_out(denom_in, denom_out, forward.amount_out).unwrap();
+				// both directions round against the trader, by a few units at most
+				let gap = back.amount_in.abs_diff(amount_in);
+				assert!(gap <= amount_in / 100_000 + 3, "{} for {}", back.amount_in, amount_in);
+				assert_eq!(back.amount_out, forward.amount_out);
+			}
+		}
+	}
+
+	#[test]
+	fn scaling_factors_bring_reserves_to_a_common_scale() {
+		// 6 decimal usdc against 18 decimal dai, both worth a dollar
+		let assets = vec![asset("uusdc", 10u128.pow(12), 1), asset("wei-dai", 10u128.pow(24), 10u128.pow(12))];
+		let pool = StableswapPool::new(assets, 100, Decimal::ZERO).unwrap();
+		let result = pool.swap_exact_in("uusdc", "wei-dai", 1_000_000).unwrap();
+		let expected = 10u128.pow(18);
+		assert!(expected - result.amount_out < expected / 1_000_000, "{}", result.amount_out);
+	}
+
+	#[test]
+	fn scaling_factors_need_not_divide_each_other() {
+		// 3 and 2 units of each token are one scaled unit, so a balanced pool
+		// trades 3 `a` for 2 `b`; flooring 3 / 2 would price it 1:1 instead
+		let assets = vec![asset("a", 3 * 10u128.pow(12), 3), asset("b", 2 * 10u128.pow(12), 2)];
+		let pool = StableswapPool::new(assets, 100, Decimal::ZERO).unwrap();
+		assert_eq!(pool.multipliers(), Ok(vec![2, 3]));
+		assert_eq!(pool.spot_price("a", "b"), Decimal::from_ratio(2, 3, Rounding::Down));
+		assert_eq!(pool.spot_price("b", "a"), Decimal::from_ratio(3, 2, Rounding::Down));
+
+		let result = pool.swap_exact_in("a", "b", 3_000_000).unwrap();
+		assert!((1_999_990..=2_000_000).contains(&result.amount_out), "{}", result.amount_out);
+		let back = pool.swap_exact_out("a", "b", result.amount_out).unwrap();
+		assert!(back.amount_in.abs_diff(3_000_000) <= 3, "{}", back.amount_in);
+
+		// factors sharing a divisor are brought to their least common multiple
+		let assets = vec![asset("a", 10, 4), asset("b", 10, 6), asset("c", 10, 9)];
+		let pool = StableswapPool::new(assets, 100, Decimal::ZERO).unwrap();
+		assert_eq!(pool.multipliers(), Ok(vec![9, 6, 4]));
+	}
+
+	#[test]
+	fn overflow_is_an_error() {
+		let assets = vec![asset("a", u128::MAX / 2, 1), asset("b", u128::MAX / 2, 1)];
+		let pool = StableswapPool::new(assets, 100, Decimal::ZERO).unwrap();
+		assert_eq!(pool.swap_exact_in("a", "b", 1_000), Err(FeanorError::MathOverflow));
+		assert_eq!(solve_reserve(&[u128::MAX, 1], 200, 10, 0, u128::MAX, 1), Err(FeanorError::MathOverflow));
+
+		// coprime factors whose least common multiple exceeds a u128
+		let assets = vec![asset("a", 1, 10u128.pow(20)), asset("b", 1, 10u128.pow(20) + 1)];
+		let pool = StableswapPool::new(assets, 100, Decimal::ZERO).unwrap();
+		assert_eq!(pool.spot_price("a", "b"), Err(FeanorError::MathOverflow));
+	}
+}
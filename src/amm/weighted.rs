@@ -0,0 +1,317 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	amm::{price_impact, Pool, PoolAsset, SwapResult},
+	error::FeanorError,
+	math::{mul_div, Decimal, Rounding},
+};
+
+// Osmosis GAMM balancer pool with arbitrary weights
+//
+// implements the balancer invariant prod(balance_i ^ weight_i) = k the same way
+// the chain does; with equal weights it reduces to `ConstantProduct`
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct WeightedPool {
+	pub assets: Vec<PoolAsset>,
+	pub swap_fee: Decimal,
+}
+
+impl WeightedPool {
+	pub fn new(assets: Vec<PoolAsset>, swap_fee: Decimal) -> Result<Self, FeanorError> {
+		if assets.len() < 2 || swap_fee >= Decimal::ONE {
+			return Err(FeanorError::InvalidPool);
+		}
+		if assets.iter().any(|asset| asset.reserve == 0 || asset.weight == 0) {
+			return Err(FeanorError::InvalidPool);
+		}
+		Ok(WeightedPool { assets, swap_fee })
+	}
+
+	fn asset(&self, denom: &str) -> Result<&PoolAsset, FeanorError> {
+		self.assets
+			.iter()
+			.find(|asset| asset.denom == denom)
+			.ok_or(FeanorError::InvalidPool)
+	}
+
+	fn sides(&self, denom_in: &str, denom_out: &str) -> Result<(&PoolAsset, &PoolAsset), FeanorError> {
+		if denom_in == denom_out {
+			return Err(FeanorError::InvalidPool);
+		}
+		Ok((self.asset(denom_in)?, self.asset(denom_out)?))
+	}
+}
+
+impl Pool for WeightedPool {
+	fn tokens(&self) -> Vec<&str> {
+		self.assets.iter().map(|asset| asset.denom.as_str()).collect()
+	}
+
+	fn fee(&self) -> Decimal {
+		self.swap_fee
+	}
+
+	/// units of `denom_out` per unit of `denom_in`, weights included
+	fn spot_price(&self, denom_in: &str, denom_out: &str) -> Result<Decimal, FeanorError> {
+		let (asset_in, asset_out) = self.sides(denom_in, denom_out)?;
+		spot_price(asset_in.reserve, asset_in.weight, asset_out.reserve, asset_out.weight)
+	}
+
+	/// out amount for `amount_in` of `denom_in`, rounded down
+	fn swap_exact_in(&self, denom_in: &str, denom_out: &str, amount_in: u128) -> Result<SwapResult, FeanorError> {
+		let (asset_in, asset_out) = self.sides(denom_in, denom_out)?;
+
+		let amount_in_after_fee = Decimal::ONE
+			.checked_sub(self.swap_fee)?
+			.mul_int(amount_in, Rounding::Down)?;
+		let amount_out = out_given_in(asset_in, asset_out, amount_in_after_fee)?;
+
+		swap_result(asset_in, asset_out, amount_in, amount_in_after_fee, amount_out)
+	}
+
+	/// in amount of `denom_in` needed to receive `amount_out` of `denom_out`, rounded up
+	fn swap_exact_out(&self, denom_in: &str, denom_out: &str, amount_out: u128) -> Result<SwapResult, FeanorError> {
+		let (asset_in, asset_out) = self.sides(denom_in, denom_out)?;
+
+		let amount_in_after_fee = in_given_out(asset_in, asset_out, amount_out)?;
+		let amount_in = Decimal::ONE
+			.checked_sub(self.swap_fee)?
+			.div_int(amount_in_after_fee, Rounding::Up)?;
+
+		swap_result(asset_in, asset_out, amount_in, amount_in_after_fee, amount_out)
+	}
+}
+
+/// swap outcome of a balancer pool, the fee staying in the pool
+pub(crate) fn swap_result(
+	asset_in: &PoolAsset,
+	asset_out: &PoolAsset,
+	amount_in: u128,
+	amount_in_after_fee: u128,
+	amount_out: u128,
+	) -> Result<SwapResult, FeanorError> {
+	let reserve_in_after = asset_in.reserve.checked_add(amount_in).ok_or(FeanorError::MathOverflow)?;
+	let reserve_out_after = asset_out.reserve.checked_sub(amount_out).ok_or(FeanorError::InsufficientLiquidity)?;
+	if reserve_out_after == 0 {
+		return Err(FeanorError::InsufficientLiquidity);
+	}
+
+	let spot_price_before = spot_price(asset_in.reserve, asset_in.weight, asset_out.reserve, asset_out.weight)?;
+	let spot_price_after = spot_price(reserve_in_after, asset_in.weight, reserve_out_after, asset_out.weight)?;
+
+	Ok(SwapResult {
+		amount_in,
+		amount_out,
+		fee_paid: amount_in - amount_in_after_fee,
+		spot_price_before,
+		spot_price_after,
+		price_impact: price_impact(spot_price_before, amount_in_after_fee, amount_out)?,
+	})
+}
+
+/// `(reserve_out / weight_out) / (reserve_in / weight_in)`
+pub(crate) fn spot_price(
+	reserve_in: u128,
+	weight_in: u128,
+	reserve_out: u128,
+	weight_out: u128,
+	) -> Result<Decimal, FeanorError> {
+	let reserve_ratio = Decimal::from_ratio(reserve_out, reserve_in, Rounding::Down)?;
+	let weight_ratio = Decimal::from_ratio(weight_in, weight_out, Rounding::Down)?;
+	reserve_ratio.checked_mul(weight_ratio)
+}
+
+/// `reserve_out * (1 - (reserve_in / (reserve_in + in)) ^ (weight_in / weight_out))`
+pub fn out_given_in(asset_in: &PoolAsset, asset_out: &PoolAsset, amount_in_after_fee: u128) -> Result<u128, FeanorError> {
+	let reserve_in_after = asset_in
+		.reserve
+		.checked_add(amount_in_after_fee)
+		.ok_or(FeanorError::MathOverflow)?;
+
+	// equal weights are the x * y = k case, computed exactly
+	if asset_in.weight == asset_out.weight {
+		return mul_div(asset_out.reserve, amount_in_after_fee, reserve_in_after, Rounding::Down)
+			.ok_or(FeanorError::MathOverflow);
+	}
+	pow_out_given_in(asset_in, asset_out, reserve_in_after)
+}
+
+// the general balancer formula, through `pow_frac`
+
+fn pow_out_given_in(asset_in: &PoolAsset, asset_out: &PoolAsset, reserve_in_after: u128) -> Result<u128, FeanorError> {
+	let base = Decimal::from_ratio(asset_in.reserve, reserve_in_after, Rounding::Up)?;
+	let exp = Decimal::from_ratio(asset_in.weight, asset_out.weight, Rounding::Down)?;
+	let remaining = base.pow_frac(exp)?.min(Decimal::ONE);
+	Decimal::ONE.checked_sub(remaining)?.mul_int(asset_out.reserve, Rounding::Down)
+}
+
+/// `reserve_in * ((reserve_out / (reserve_out - out)) ^ (weight_out / weight_in) - 1)`
+pub fn in_given_out(asset_in: &PoolAsset, asset_out: &PoolAsset, amount_out: u128) -> Result<u128, FeanorError> {
+	let reserve_out_after = asset_out.reserve.checked_sub(amount_out).ok_or(FeanorError::InsufficientLiquidity)?;
+	if reserve_out_after == 0 {
+		return Err(FeanorError::InsufficientLiquidity);
+	}
+
+	if asset_in.weight == asset_out.weight {
+		return mul_div(asset_in.reserve, amount_out, reserve_out_after, Rounding::Up)
+			.ok_or(FeanorError::MathOverflow);
+	}
+	pow_in_given_out(asset_in, asset_out, reserve_out_after)
+}
+
+fn pow_in_given_out(asset_in: &PoolAsset, asset_out: &PoolAsset, reserve_out_after: u128) -> Result<u128, FeanorError> {
+	let base = Decimal::from_ratio(asset_out.reserve, reserve_out_after, Rounding::Up)?;
+	let exp = Decimal::from_ratio(asset_out.weight, asset_in.weight, Rounding::Up)?;
+	let growth = base.pow_frac(exp)?.checked_sub(Decimal::ONE)?;
+	growth.mul_int(asset_in.reserve, Rounding::Up)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::amm::ConstantProduct;
+
+	fn asset(denom: &str, reserve: u128, weight: u128) -> PoolAsset {
+		PoolAsset { denom: denom.into(), reserve, weight }
+	}
+
+	// xorshift, so the sweep is reproducible without a property testing crate
+	struct Rng(u64);
+
+	impl Rng {
+		fn next(&mut self) -> u64 {
+			self.0 ^= self.0 << 13;
+			self.0 ^= self.0 >> 7;
+			self.0 ^= self.0 << 17;
+			self.0
+		}
+
+		fn range(&mut self, low: u128, high: u128) -> u128 {
+			low + self.next() as u128 % (high - low)
+		}
+	}
+
+	// (reserve_in, reserve_out, amount, fee in bps, amount in after fee, amount out)
+	// worked out by hand from out = y * x_in / (x + x_in) rounded down and
+	// x_in = x * out / (y - out) rounded up, the fee then grossed up
+	const EXACT_IN: [(u128, u128, u128, u64, u128, u128); 4] = [
+		(1_000_000, 2_000_000, 10_000, 30, 9_970, 19_743),
+		(5_000_000_000, 5_000_000_000, 1_234_567, 0, 1_234_567, 1_234_262),
+		(
+			123_456_789_012_345_678_901,
+			987_654_321_098_765_432_109,
+			1_000_000_000_000_000_000,
+			20,
+			998_000_000_000_000_000,
+			7_919_976_565_616_855_560,
+		),
+		(1_000, 1_000, 999_000, 100, 989_010, 998),
+	];
+
+	// (reserve_in, reserve_out, amount out, fee in bps, amount in after fee, amount in)
+	const EXACT_OUT: [(u128, u128, u128, u64, u128, u128); 4] = [
+		(1_000_000, 2_000_000, 19_000, 30, 9_592, 9_621),
+		(5_000_000_000, 5_000_000_000, 1_234_567, 0, 1_234_872, 1_234_872),
+		(
+			123_456_789_012_345_678_901,
+			987_654_321_098_765_432_109,
+			1_000_000_000_000_000_000,
+			20,
+			125_126_689_634_177_853,
+			125_377_444_523_224_302,
+		),
+		(1_000, 1_000, 999, 100, 999_000, 1_009_091),
+	];
+
+	#[test]
+	fn equal_weights_follow_the_constant_product_closed_form() {
+		for (reserve_in, reserve_out, amount_in, fee, after_fee, amount_out) in EXACT_IN {
+			let assets = [asset("uatom", reserve_in, 5), asset("uosmo", reserve_out, 5)];
+			let weighted = WeightedPool::new(assets.to_vec(), Decimal::bps(fee)).unwrap();
+			let constant = ConstantProduct::new(assets, Decimal::bps(fee)).unwrap();
+			for result in [
+				weighted.swap_exact_in("uatom", "uosmo", amount_in).unwrap(),
+				constant.swap_exact_in("uatom", "uosmo", amount_in).unwrap(),
+			] {
+				assert_eq!(result.amount_out, amount_out, "{} in", amount_in);
+				assert_eq!(result.fee_paid, amount_in - after_fee);
+			}
+		}
+		for (reserve_in, reserve_out, amount_out, fee, after_fee, amount_in) in EXACT_OUT {
+			let assets = [asset("uatom", reserve_in, 5), asset("uosmo", reserve_out, 5)];
+			let weighted = WeightedPool::new(assets.to_vec(), Decimal::bps(fee)).unwrap();
+			let constant = ConstantProduct::new(assets, Decimal::bps(fee)).unwrap();
+			for result in [
+				weighted.swap_exact_out("uatom", "uosmo", amount_out).unwrap(),
+				constant.swap_exact_out("uatom", "uosmo", amount_out).unwrap(),
+			] {
+				assert_eq!(result.amount_in, amount_in, "{} out", amount_out);
+				assert_eq!(result.fee_paid, amount_in - after_fee);
+			}
+		}
+	}
+
+	#[test]
+	fn equal_weights_match_the_closed_form_over_random_pools() {
+		// reserves and amounts below 10^18 keep `y * x_in` within a u128
+		let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+		for _ in 0..1000 {
+			let reserve_in = rng.range(1_000, 10u128.pow(18));
+			let reserve_out = rng.range(1_000, 10u128.pow(18));
+			let weight = rng.range(1, 10u128.pow(20));
+			let pool = WeightedPool::new(
+				vec![asset("uatom", reserve_in, weight), asset("uosmo", reserve_out, weight)],
+				Decimal::ZERO,
+			)
+			.unwrap();
+
+			let amount_in = rng.range(1, reserve_in);
+			let expected = reserve_out * amount_in / (reserve_in + amount_in);
+			assert_eq!(pool.swap_exact_in("uatom", "uosmo", amount_in).unwrap().amount_out, expected);
+
+			let amount_out = rng.range(1, reserve_out);
+			let expected = (reserve_in * amount_out).div_ceil(reserve_out - amount_out);
+			assert_eq!(pool.swap_exact_out("uatom", "uosmo", amount_out).unwrap().amount_in, expected);
+		}
+	}
+
+	#[test]
+	fn pow_frac_path_with_equal_weights_matches_constant_product() {
+		// the exponent is exactly one so the only error is the 18 digit base,
+		// at most reserve * 10^-18 plus a unit of rounding
+		let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+		for _ in 0..1000 {
+			let weight = rng.range(1, 1000);
+			let asset_in = asset("uatom", rng.range(1_000, 10u128.pow(24)), weight);
+			let asset_out = asset("uosmo", rng.range(1_000, 10u128.pow(24)), weight);
+			let tolerance = asset_out.reserve.max(asset_in.reserve) / 10u128.pow(18) + 1;
+
+			let amount_in = rng.range(1, asset_in.reserve);
+			let exact = out_given_in(&asset_in, &asset_out, amount_in).unwrap();
+			let pow = pow_out_given_in(&asset_in, &asset_out, asset_in.reserve + amount_in).unwrap();
+			assert!(exact.abs_diff(pow) <= tolerance, "out {} vs {}", exact, pow);
+
+			// below half the reserve the base stays under two and is not inverted
+			let amount_out = rng.range(1, asset_out.reserve / 2);
+			let exact = in_given_out(&asset_in, &asset_out, amount_out).unwrap();
+			let pow = pow_in_given_out(&asset_in, &asset_out, asset_out.reserve - amount_out).unwrap();
+			assert!(exact.abs_diff(pow) <= tolerance, "in {} vs {}", exact, pow);
+		}
+	}
+
+	#[test]
+	fn unequal_weights_follow_the_balancer_formula() {
+		// 1:2 weights take the fractional series, precise to about 10^-8
+		let asset_in = asset("uatom", 1_000_000_000_000, 1);
+		let asset_out = asset("uosmo", 3_000_000_000_000, 2);
+		for amount_in in [1_000_000u128, 10_000_000_000, 500_000_000_000] {
+			let expected = 3e12 * (1.0 - (1e12 / (1e12 + amount_in as f64)).sqrt());
+			let out = out_given_in(&asset_in, &asset_out, amount_in).unwrap();
+			assert!((out as f64 - expected).abs() <= 3e12 * 1e-8, "{} vs {}", out, expected);
+			// rounded against the trader both ways
+			let back = in_given_out(&asset_in, &asset_out, out).unwrap();
+			assert!(back as f64 >= amount_in as f64 * (1.0 - 1e-6), "{} vs {}", back, amount_in);
+		}
+	}
+}
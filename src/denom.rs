@@ -0,0 +1,318 @@
+use std::{fmt, str::FromStr};
+
+use bech32::{FromBase32, ToBase32, Variant};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+
+use crate::error::FeanorError;
+
+// typed addresses and denoms
+//
+// the dashboard passes `osmo1...` contracts and `ibc/...` denoms around as
+// opaque strings; parsing them here rejects malformed values before they end
+// up in a message
+
+/// length limits of Cosmos SDK denoms and token factory subdenoms
+const MAX_DENOM_LEN: usize = 128;
+const MAX_SUBDENOM_LEN: usize = 44;
+const IBC_PREFIX: &str = "ibc/";
+const FACTORY_PREFIX: &str = "factory/";
+
+/// a validated bech32 account or contract address, kept in lowercase
+#[derive(Clone, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct Addr(String);
+
+impl Addr {
+	/// bech32 address of `bytes` under `prefix`
+	pub fn from_bytes(prefix: &str, bytes: &[u8]) -> Result<Self, FeanorError> {
+		bech32::encode(prefix, bytes.to_base32(), Variant::Bech32)
+			.map(Addr)
+			.map_err(|_| FeanorError::InvalidAddress)
+	}
+
+	/// parse `value` and check that it belongs to the `prefix` chain
+	pub fn parse_with_prefix(value: &str, prefix: &str) -> Result<Self, FeanorError> {
+		let addr: Addr = value.parse()?;
+		if addr.prefix() != prefix {
+			return Err(FeanorError::InvalidAddress);
+		}
+		Ok(addr)
+	}
+
+	/// human readable part, e.g. `osmo`
+	pub fn prefix(&self) -> &str {
+		// `1` is the last separator, the data part never contains it
+		self.0.rsplit_once('1').map_or("", |(prefix, _)| prefix)
+	}
+
+	/// decoded address bytes, 20 for accounts and 32 for contracts
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let (_, data, _) = bech32::decode(&self.0).expect("address validated on construction");
+		Vec::<u8>::from_base32(&data).expect("address validated on construction")
+	}
+
+	pub fn as_str(&self) -> &str {
+		&self.0
+	}
+}
+
+impl FromStr for Addr {
+	type Err = FeanorError;
+
+	fn from_str(value: &str) -> Result<Self, Self::Err> {
+		// mixed case is rejected by the decoder
+		let (prefix, data, variant) = bech32::decode(value).map_err(|_| FeanorError::InvalidAddress)?;
+		let bytes = Vec::<u8>::from_base32(&data).map_err(|_| FeanorError::InvalidAddress)?;
+		if variant != Variant::Bech32 || !matches!(bytes.len(), 20 | 32) {
+			return Err(FeanorError::InvalidAddress);
+		}
+		Addr::from_bytes(&prefix, &bytes)
+	}
+}
+
+impl fmt::Display for Addr {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.0)
+	}
+}
+
+/// a denom of the bank module or a CW20 token
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Denom {
+	/// base denom of the chain or a module, e.g. `uosmo` or `gamm/pool/1`
+	Native(String),
+	/// `ibc/<HASH>`, the sha256 of the transfer trace
+	Ibc { hash: [u8; 32] },
+	/// `factory/<creator>/<subdenom>`
+	Factory { creator: Addr, subdenom: String },
+	/// CW20 token, denominated by its contract address
+	Cw20 { addr: Addr },
+}
+
+impl Denom {
+	/// IBC voucher of `base_denom` received over `port`/`channel`
+	pub fn ibc(port: &str, channel: &str, base_denom: &str) -> Self {
+		Denom::Ibc {
+			hash: ibc_denom_hash(port, channel, base_denom),
+		}
+	}
+
+	pub fn is_cw20(&self) -> bool {
+		matches!(self, Denom::Cw20 { .. })
+	}
+}
+
+/// `sha256("{port}/{channel}/{base_denom}")`, the hash of an `ibc/` denom
+pub fn ibc_denom_hash(port: &str, channel: &str, base_denom: &str) -> [u8; 32] {
+	ibc_denom_hash_of_path(&format!("{}/{}/{}", port, channel, base_denom))
+}
+
+/// hash of a full trace such as `transfer/channel-0/transfer/channel-1/uatom`
+pub fn ibc_denom_hash_of_path(path: &str) -> [u8; 32] {
+	Sha256::digest(path).into()
+}
+
+impl FromStr for Denom {
+	type Err = FeanorError;
+
+	fn from_str(value: &str) -> Result<Self, Self::Err> {
+		if let Some(hash) = value.strip_prefix(IBC_PREFIX) {
+			return parse_hash(hash).map(|hash| Denom::Ibc { hash });
+		}
+		if let Some(rest) = value.strip_prefix(FACTORY_PREFIX) {
+			let (creator, subdenom) = rest.split_once('/').ok_or(FeanorError::InvalidDenom)?;
+			let creator = creator.parse().map_err(|_| FeanorError::InvalidDenom)?;
+			if subdenom.len() > MAX_SUBDENOM_LEN || !is_valid_denom(value) {
+				return Err(FeanorError::InvalidDenom);
+			}
+			return Ok(Denom::Factory {
+				creator,
+				subdenom: subdenom.to_string(),
+			});
+		}
+		if let Ok(addr) = value.parse() {
+			return Ok(Denom::Cw20 { addr });
+		}
+		if !is_valid_denom(value) {
+			return Err(FeanorError::InvalidDenom);
+		}
+		Ok(Denom::Native(value.to_string()))
+	}
+}
+
+impl fmt::Display for Denom {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Denom::Native(denom) => f.write_str(denom),
+			Denom::Ibc { hash } => {
+				f.write_str(IBC_PREFIX)?;
+				hash.iter().try_for_each(|byte| write!(f, "{:02X}", byte))
+			}
+			Denom::Factory { creator, subdenom } => write!(f, "{}{}/{}", FACTORY_PREFIX, creator, subdenom),
+			Denom::Cw20 { addr } => write!(f, "{}", addr),
+		}
+	}
+}
+
+// denoms travel as their string form
+
+impl Serialize for Denom {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(self)
+	}
+}
+
+impl<'de> Deserialize<'de> for Denom {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let value = String::deserialize(deserializer)?;
+		value.parse().map_err(|_| de::Error::custom(format!("invalid denom '{}'", value)))
+	}
+}
+
+impl Serialize for Addr {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(&self.0)
+	}
+}
+
+impl<'de> Deserialize<'de> for Addr {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let value = String::deserialize(deserializer)?;
+		value.parse().map_err(|_| de::Error::custom(format!("invalid address '{}'", value)))
+	}
+}
+
+/// Cosmos SDK denom syntax, `[a-zA-Z][a-zA-Z0-9/:._-]{2,127}`
+fn is_valid_denom(denom: &str) -> bool {
+	let mut chars = denom.chars();
+	let starts_with_letter = chars.next().is_some_and(|c| c.is_ascii_alphabetic());
+	starts_with_letter
+		&& (3..=MAX_DENOM_LEN).contains(&denom.len())
+		&& chars.all(|c| c.is_ascii_alphanumeric() || "/:._-".contains(c))
+}
+
+/// 64 hex digits, the chain writes them in uppercase but accepts either case
+fn parse_hash(hex: &str) -> Result<[u8; 32], FeanorError> {
+	if hex.len() != 64 || !hex.bytes().all(|c| c.is_ascii_hexdigit()) {
+		return Err(FeanorError::InvalidDenom);
+	}
+	let mut hash = [0u8; 32];
+	for (byte, pair) in hash.iter_mut().zip(hex.as_bytes().chunks(2)) {
+		let pair = std::str::from_utf8(pair).expect("checked to be hex digits");
+		*byte = u8::from_str_radix(pair, 16).expect("checked to be hex digits");
+	}
+	Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const ACCOUNT: &str = "osmo1qypqxpq9qcrsszg2pvxq6rs0zqg3yyc5helwsw";
+	const CONTRACT: &str = "osmo1qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurstc4vrk";
+	const ATOM: &str = "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2";
+
+	#[test]
+	fn parses_accounts_and_contracts() {
+		let account: Addr = ACCOUNT.parse().unwrap();
+		assert_eq!(account.prefix(), "osmo");
+		assert_eq!(account.to_bytes(), (1..=20).collect::<Vec<u8>>());
+		assert_eq!(Addr::from_bytes("osmo", &account.to_bytes()).unwrap(), account);
+
+		let contract: Addr = CONTRACT.parse().unwrap();
+		assert_eq!(contract.to_bytes(), vec![7; 32]);
+		assert_eq!(contract.to_string(), CONTRACT);
+
+		// same bytes under another chain
+		let cosmos = Addr::from_bytes("cosmos", &account.to_bytes()).unwrap();
+		assert_eq!(cosmos.as_str(), "cosmos1qypqxpq9qcrsszg2pvxq6rs0zqg3yyc5lzv7xu");
+		assert_eq!(Addr::parse_with_prefix(ACCOUNT, "osmo").unwrap(), account);
+		assert_eq!(
+			Addr::parse_with_prefix(cosmos.as_str(), "osmo"),
+			Err(FeanorError::InvalidAddress)
+		);
+	}
+
+	#[test]
+	fn rejects_bad_checksums_and_lengths() {
+		// last checksum character changed
+		let corrupted = format!("{}x", &ACCOUNT[..ACCOUNT.len() - 1]);
+		assert_eq!(corrupted.parse::<Addr>(), Err(FeanorError::InvalidAddress));
+		// one data character swapped
+		let swapped = ACCOUNT.replacen("qypq", "qypr", 1);
+		assert_eq!(swapped.parse::<Addr>(), Err(FeanorError::InvalidAddress));
+		// mixed case
+		let mixed = format!("OSMO{}", &ACCOUNT[4..]);
+		assert_eq!(mixed.parse::<Addr>(), Err(FeanorError::InvalidAddress));
+		// valid bech32 of 16 bytes
+		assert_eq!(
+			"osmo1qypqxpq9qcrsszg2pvxq6rs0zqnm32h3".parse::<Addr>(),
+			Err(FeanorError::InvalidAddress)
+		);
+		assert_eq!("osmo".parse::<Addr>(), Err(FeanorError::InvalidAddress));
+	}
+
+	#[test]
+	fn parses_every_kind_of_denom() {
+		assert_eq!("uosmo".parse::<Denom>().unwrap(), Denom::Native("uosmo".to_string()));
+		assert_eq!(
+			"gamm/pool/1".parse::<Denom>().unwrap(),
+			Denom::Native("gamm/pool/1".to_string())
+		);
+
+		let atom: Denom = ATOM.parse().unwrap();
+		assert_eq!(atom, Denom::ibc("transfer", "channel-0", "uatom"));
+		assert_eq!(atom.to_string(), ATOM);
+		// lowercase hashes are accepted and written back in uppercase
+		assert_eq!(ATOM.to_lowercase().parse::<Denom>().unwrap(), atom);
+
+		let factory = format!("factory/{}/ufoo", ACCOUNT);
+		assert_eq!(
+			factory.parse::<Denom>().unwrap(),
+			Denom::Factory {
+				creator: ACCOUNT.parse().unwrap(),
+				subdenom: "ufoo".to_string(),
+			}
+		);
+
+		let cw20: Denom = CONTRACT.parse().unwrap();
+		assert!(cw20.is_cw20());
+		assert_eq!(cw20.to_string(), CONTRACT);
+	}
+
+	#[test]
+	fn rejects_malformed_denoms() {
+		for value in [
+			"",
+			"u",
+			"1osmo",
+			"uosmo!",
+			"ibc/27394FB092",
+			"ibc/ZZ394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2",
+			"factory/ufoo",
+		] {
+			assert_eq!(value.parse::<Denom>(), Err(FeanorError::InvalidDenom), "{}", value);
+		}
+		// a creator with a broken checksum
+		let creator = format!("{}x", &ACCOUNT[..ACCOUNT.len() - 1]);
+		let factory = format!("factory/{}/ufoo", creator);
+		assert_eq!(factory.parse::<Denom>(), Err(FeanorError::InvalidDenom));
+		let long = format!("factory/{}/{}", ACCOUNT, "a".repeat(MAX_SUBDENOM_LEN + 1));
+		assert_eq!(long.parse::<Denom>(), Err(FeanorError::InvalidDenom));
+		assert_eq!(
+			"a".repeat(MAX_DENOM_LEN + 1).parse::<Denom>(),
+			Err(FeanorError::InvalidDenom)
+		);
+	}
+
+	#[test]
+	fn denoms_travel_as_strings() {
+		let denoms: Vec<Denom> = serde_json::from_str(&format!(r#"["uosmo","{}","{}"]"#, ATOM, CONTRACT)).unwrap();
+		assert_eq!(
+			serde_json::to_string(&denoms).unwrap(),
+			format!(r#"["uosmo","{}","{}"]"#, ATOM, CONTRACT)
+		);
+		assert!(serde_json::from_str::<Denom>(r#""1bad""#).is_err());
+		assert!(serde_json::from_str::<Addr>(r#""osmo1bad""#).is_err());
+	}
+}
@@ -0,0 +1,174 @@
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use solana_program::{
+	decode_error::DecodeError,
+	msg,
+	program_error::{PrintProgramError, ProgramError},
+};
+use thiserror::Error;
+
+// errors returned by the feanor program
+//
+// the discriminant of each variant is the `ProgramError::Custom` code seen by
+// clients, so new variants are only ever appended
+
+#[derive(Error, FromPrimitive, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FeanorError {
+	/// instruction data was empty or could not be decoded
+	#[error("Invalid instruction data")]
+	InvalidInstruction,
+	/// the leading tag does not match any `FeanorInstruction` variant
+	#[error("Unknown instruction tag")]
+	UnknownInstruction,
+	/// the route is empty or its steps do not chain
+	#[error("Invalid route")]
+	InvalidRoute,
+	/// the route returns less than the loan plus the required profit
+	#[error("Route is not profitable")]
+	UnprofitableRoute,
+	/// the executed amount is outside the configured slippage tolerance
+	#[error("Slippage tolerance exceeded")]
+	SlippageExceeded,
+	/// the signer does not hold a Varda NFT
+	#[error("Varda NFT ownership check failed")]
+	NftGateFailed,
+	/// the price used to evaluate the route is too old
+	#[error("Stale price")]
+	StalePrice,
+	/// an arithmetic operation overflowed or divided by zero
+	#[error("Math overflow")]
+	MathOverflow,
+	/// the vault account is not the PDA derived from the owner
+	#[error("Invalid vault address")]
+	InvalidVaultAddress,
+	/// the vault account already holds an initialized vault
+	#[error("Vault already initialized")]
+	VaultAlreadyInitialized,
+	/// the signer is not the owner of the vault
+	#[error("Signer is not the vault owner")]
+	VaultOwnerMismatch,
+	/// the vault treasury cannot cover the requested amount
+	#[error("Insufficient vault funds")]
+	InsufficientVaultFunds,
+	/// the gate config account is missing or was never configured
+	#[error("Varda NFT gate is not configured")]
+	GateNotConfigured,
+	/// the signer is not allowed to change the gate config
+	#[error("Signer is not the gate authority")]
+	GateAuthorityMismatch,
+	/// the held NFT has no verified Varda collection in its metadata
+	#[error("NFT collection is not verified")]
+	NftCollectionNotVerified,
+	/// a decimal string could not be parsed or has more than 18 fractional digits
+	#[error("Invalid decimal")]
+	InvalidDecimal,
+	/// the pool parameters cannot be simulated, e.g. empty reserves or an unknown denom
+	#[error("Invalid pool")]
+	InvalidPool,
+	/// the pool cannot fill the requested amount
+	#[error("Insufficient pool liquidity")]
+	InsufficientLiquidity,
+	/// a route step references a pool that was not loaded
+	#[error("Pool not found")]
+	PoolNotFound,
+	/// the mnemonic is not a valid BIP39 English phrase
+	#[error("Invalid mnemonic")]
+	InvalidMnemonic,
+	/// the derivation path is malformed or not a BIP44 path
+	#[error("Invalid derivation path")]
+	InvalidDerivationPath,
+	/// the private key is not a valid secp256k1 scalar
+	#[error("Invalid private key")]
+	InvalidKey,
+	/// the address is not valid bech32 or has an unexpected prefix
+	#[error("Invalid address")]
+	InvalidAddress,
+	/// the denom is not a valid native, IBC, token factory or CW20 denom
+	#[error("Invalid denom")]
+	InvalidDenom,
+	/// the asset list is malformed or an entry contradicts its own denom
+	#[error("Invalid asset list")]
+	InvalidAssetList,
+	/// the denom is not in the asset registry
+	#[error("Unknown asset")]
+	UnknownAsset,
+	/// the loan size bounds are zero or inverted
+	#[error("Invalid loan bounds")]
+	InvalidBounds,
+}
+
+impl From<FeanorError> for ProgramError {
+	fn from(e: FeanorError) -> Self {
+		ProgramError::Custom(e as u32)
+	}
+}
+
+impl<T> DecodeError<T> for FeanorError {
+	fn type_of() -> &'static str {
+		"FeanorError"
+	}
+}
+
+impl PrintProgramError for FeanorError {
+	fn print<E>(&self)
+	where
+		E: 'static + std::error::Error + DecodeError<E> + PrintProgramError + FromPrimitive,
+	{
+		msg!("Error: {}", self);
+	}
+}
+
+impl FeanorError {
+	/// map a `ProgramError::Custom` code back to the error that produced it
+	pub fn from_code(code: u32) -> Option<Self> {
+		Self::from_u32(code)
+	}
+}
+
+/// readable message for a custom error code, for client tooling
+pub fn decode_error_code(code: u32) -> Option<String> {
+	FeanorError::from_code(code).map(|e| e.to_string())
+}
+
+/// readable message for any program error, falling back to its debug form
+pub fn describe_program_error(error: &ProgramError) -> String {
+	match error {
+		ProgramError::Custom(code) => decode_error_code(*code)
+			.unwrap_or_else(|| format!("Unknown custom error {}", code)),
+		other => format!("{:?}", other),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn codes_are_the_variant_order() {
+		assert_eq!(ProgramError::from(FeanorError::InvalidInstruction), ProgramError::Custom(0));
+		assert_eq!(ProgramError::from(FeanorError::NftGateFailed), ProgramError::Custom(5));
+		assert_eq!(ProgramError::from(FeanorError::UnknownAsset), ProgramError::Custom(25));
+	}
+
+	#[test]
+	fn decodes_every_code_back_to_its_error() {
+		let mut code = 0;
+		while let Some(error) = FeanorError::from_code(code) {
+			assert_eq!(ProgramError::from(error), ProgramError::Custom(code));
+			code += 1;
+		}
+		assert_eq!(code, FeanorError::InvalidBounds as u32 + 1);
+		assert_eq!(decode_error_code(code), None);
+	}
+
+	#[test]
+	fn describes_custom_and_builtin_errors() {
+		assert_eq!(decode_error_code(7).as_deref(), Some("Math overflow"));
+		assert_eq!(
+			describe_program_error(&FeanorError::VaultOwnerMismatch.into()),
+			"Signer is not the vault owner"
+		);
+		assert_eq!(describe_program_error(&ProgramError::Custom(1000)), "Unknown custom error 1000");
+		assert_eq!(describe_program_error(&ProgramError::NotEnoughAccountKeys), "NotEnoughAccountKeys");
+	}
+}
@@ -0,0 +1,344 @@
+use std::{
+	collections::BTreeMap,
+	thread,
+	time::Duration,
+};
+
+use serde::Deserialize;
+
+use crate::{
+	amm::concentrated::Tick,
+	feeds::{
+		http::HttpClient, lcd::parse_pool_response, parse_decimal, parse_int, FetchError, ParseError, PoolModel,
+		PoolSnapshot,
+	},
+};
+
+// pool state read straight from an Osmosis LCD endpoint
+//
+// the aggregators quote prices that lag the chain; this reads the exact
+// reserves, weights, fees and, for CL pools, every initialized tick, a batch
+// of pools at a time and retrying transient failures
+
+pub const OSMOSIS_LCD_URL: &str = "https://lcd.osmosis.zone";
+
+#[derive(Clone, Debug)]
+pub struct ChainReaderConfig {
+	pub lcd_url: String,
+	/// pools queried concurrently
+	pub batch_size: usize,
+	/// attempts per request, the first one included
+	pub max_attempts: u32,
+	/// wait before the first retry, doubled at each following one
+	pub backoff: Duration,
+}
+
+impl Default for ChainReaderConfig {
+	fn default() -> Self {
+		ChainReaderConfig {
+			lcd_url: OSMOSIS_LCD_URL.to_string(),
+			batch_size: 8,
+			max_attempts: 3,
+			backoff: Duration::from_millis(250),
+		}
+	}
+}
+
+pub struct ChainReader<C> {
+	client: C,
+	config: ChainReaderConfig,
+}
+
+impl<C: HttpClient + Sync> ChainReader<C> {
+	pub fn new(client: C, config: ChainReaderConfig) -> Self {
+		ChainReader { client, config }
+	}
+
+	pub fn pool_url(&self, pool_id: u64) -> String {
+		format!("{}/osmosis/poolmanager/v1beta1/pools/{}", self.config.lcd_url, pool_id)
+	}
+
+	pub fn ticks_url(&self, pool_id: u64) -> String {
+		format!(
+			"{}/osmosis/concentratedliquidity/v1beta1/liquidity_per_tick_range?pool_id={}",
+			self.config.lcd_url, pool_id
+		)
+	}
+
+	/// snapshot of `pool_id`, CL pools with their ticks
+	pub fn pool(&self, pool_id: u64) -> Result<PoolSnapshot, FetchError> {
+		let mut snapshot = parse_pool_response(&self.get(&self.pool_url(pool_id))?)?;
+		if let PoolModel::Concentrated { ticks, .. } = &mut snapshot.model {
+			*ticks = parse_ticks(&self.get(&self.ticks_url(pool_id))?)?;
+		}
+		Ok(snapshot)
+	}
+
+	/// snapshots of `pool_ids` in the same order, `batch_size` requests in flight at most
+	pub fn pools(&self, pool_ids: &[u64]) -> Vec<Result<PoolSnapshot, FetchError>> {
+		let mut snapshots = Vec::with_capacity(pool_ids.len());
+		for batch in pool_ids.chunks(self.config.batch_size.max(1)) {
+			thread::scope(|scope| {
+				let handles: Vec<_> = batch
+					.iter()
+					.map(|pool_id| scope.spawn(move || self.pool(*pool_id)))
+					.collect();
+				for handle in handles {
+					let panicked = || Err(FetchError::Transport("reader thread panicked".to_string()));
+					snapshots.push(handle.join().unwrap_or_else(|_| panicked()));
+				}
+			});
+		}
+		snapshots
+	}
+
+	/// GET retried with exponential backoff on transport errors, 429 and 5xx
+	fn get(&self, url: &str) -> Result<String, FetchError> {
+		let mut backoff = self.config.backoff;
+		let mut attempt = 1;
+		loop {
+			match self.client.get(url, &[]) {
+				Err(e) if attempt < self.config.max_attempts && is_transient(&e) => {
+					thread::sleep(backoff);
+					backoff = backoff.saturating_mul(2);
+					attempt += 1;
+				}
+				result => return result,
+			}
+		}
+	}
+}
+
+fn is_transient(error: &FetchError) -> bool {
+	match error {
+		FetchError::Transport(_) => true,
+		FetchError::Status { status, .. } => *status == 429 || *status >= 500,
+		FetchError::Parse(_) | FetchError::Cache(_) | FetchError::TooManyPages { .. } => false,
+	}
+}
+
+#[derive(Deserialize)]
+struct TickRanges {
+	liquidity: Vec<TickRange>,
+}
+
+#[derive(Deserialize)]
+struct TickRange {
+	liquidity_amount: String,
+	lower_tick: String,
+	upper_tick: String,
+}
+
+/// initialized ticks from the liquidity of each position range
+///
+/// a range adds its liquidity when its lower tick is crossed left to right and
+/// removes it at its upper tick
+pub fn parse_ticks(json: &str) -> Result<Vec<Tick>, ParseError> {
+	let ranges: TickRanges = serde_json::from_str(json)?;
+	let mut net: BTreeMap<i64, i128> = BTreeMap::new();
+	for range in ranges.liquidity {
+		let liquidity = parse_decimal("liquidity_amount", &range.liquidity_amount)?;
+		let liquidity = i128::try_from(liquidity.atomics()).map_err(|_| ParseError::InvalidNumber {
+			field: "liquidity_amount",
+			value: range.liquidity_amount.clone(),
+		})?;
+		let lower: i64 = parse_int("lower_tick", &range.lower_tick)?;
+		let upper: i64 = parse_int("upper_tick", &range.upper_tick)?;
+		let overflow = || ParseError::InvalidNumber {
+			field: "liquidity_amount",
+			value: range.liquidity_amount.clone(),
+		};
+		let lower_net = net.entry(lower).or_default();
+		*lower_net = lower_net.checked_add(liquidity).ok_or_else(overflow)?;
+		let upper_net = net.entry(upper).or_default();
+		*upper_net = upper_net.checked_sub(liquidity).ok_or_else(overflow)?;
+	}
+	Ok(net
+		.into_iter()
+		.filter(|(_, liquidity_net)| *liquidity_net != 0)
+		.map(|(index, liquidity_net)| Tick { index, liquidity_net })
+		.collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		collections::{HashMap, VecDeque},
+		sync::Mutex,
+	};
+
+	use super::*;
+
+	const LCD: &str = "https://lcd.test";
+
+	/// answers each URL from its own queue of responses, counting the requests
+	#[derive(Default)]
+	struct Scripted {
+		responses: Mutex<HashMap<String, VecDeque<Result<String, FetchError>>>>,
+		requests: Mutex<Vec<String>>,
+	}
+
+	impl Scripted {
+		fn push(&self, url: &str, response: Result<String, FetchError>) {
+			self.responses.lock().unwrap().entry(url.to_string()).or_default().push_back(response);
+		}
+
+		fn requests_to(&self, url: &str) -> usize {
+			self.requests.lock().unwrap().iter().filter(|requested| *requested == url).count()
+		}
+	}
+
+	impl HttpClient for Scripted {
+		fn get(&self, url: &str, _headers: &[(&str, &str)]) -> Result<String, FetchError> {
+			self.requests.lock().unwrap().push(url.to_string());
+			let next = self.responses.lock().unwrap().get_mut(url).and_then(VecDeque::pop_front);
+			next.unwrap_or_else(|| Err(status(url, 404)))
+		}
+	}
+
+	fn status(url: &str, status: u16) -> FetchError {
+		FetchError::Status { url: url.to_string(), status }
+	}
+
+	fn balancer(pool_id: u64) -> String {
+		format!(
+			r#"{{"pool":{{"@type":"/osmosis.gamm.v1beta1.Pool","id":"{}","pool_params":{{"swap_fee":"0.002"}},
+			"pool_assets":[{{"token":{{"denom":"uatom","amount":"1000"}},"weight":"1"}},
+			{{"token":{{"denom":"uosmo","amount":"6000"}},"weight":"1"}}]}}}}"#,
+			pool_id
+		)
+	}
+
+	fn reader(client: &Scripted, batch_size: usize) -> ChainReader<&Scripted> {
+		let config = ChainReaderConfig {
+			lcd_url: LCD.to_string(),
+			batch_size,
+			backoff: Duration::ZERO,
+			..ChainReaderConfig::default()
+		};
+		ChainReader::new(client, config)
+	}
+
+	#[test]
+	fn retries_server_errors_and_rate_limits() {
+		let client = Scripted::default();
+		let reader = reader(&client, 8);
+		let url = reader.pool_url(1);
+		client.push(&url, Err(status(&url, 503)));
+		client.push(&url, Err(status(&url, 429)));
+		client.push(&url, Ok(balancer(1)));
+		assert_eq!(reader.pool(1).unwrap().pool_id, 1);
+		assert_eq!(client.requests_to(&url), 3);
+
+		// the third failure is returned
+		let url = reader.pool_url(2);
+		client.push(&url, Err(FetchError::Transport("connection reset".to_string())));
+		client.push(&url, Err(status(&url, 500)));
+		client.push(&url, Err(status(&url, 502)));
+		client.push(&url, Ok(balancer(2)));
+		assert_eq!(reader.pool(2).unwrap_err(), status(&url, 502));
+		assert_eq!(client.requests_to(&url), 3);
+	}
+
+	#[test]
+	fn client_and_parse_errors_are_not_retried() {
+		let client = Scripted::default();
+		let reader = reader(&client, 8);
+		let url = reader.pool_url(1);
+		client.push(&url, Err(status(&url, 400)));
+		client.push(&url, Ok(balancer(1)));
+		assert_eq!(reader.pool(1).unwrap_err(), status(&url, 400));
+		assert_eq!(client.requests_to(&url), 1);
+
+		let url = reader.pool_url(2);
+		client.push(&url, Ok("{\"pool\":".to_string()));
+		client.push(&url, Ok(balancer(2)));
+		assert!(matches!(reader.pool(2), Err(FetchError::Parse(ParseError::Json(_)))));
+		assert_eq!(client.requests_to(&url), 1);
+	}
+
+	#[test]
+	fn keeps_the_order_across_batches() {
+		let client = Scripted::default();
+		let reader = reader(&client, 2);
+		let ids = [7, 3, 11, 5, 9];
+		for id in ids {
+			client.push(&reader.pool_url(id), Ok(balancer(id)));
+		}
+		let results = reader.pools(&[7, 3, 11, 42, 5, 9]);
+		let found: Vec<Option<u64>> = results.iter().map(|result| result.as_ref().ok().map(|pool| pool.pool_id)).collect();
+		assert_eq!(found, vec![Some(7), Some(3), Some(11), None, Some(5), Some(9)]);
+		assert_eq!(results[3], Err(status(&reader.pool_url(42), 404)));
+	}
+
+	#[test]
+	fn reads_ticks_of_concentrated_pools_only() {
+		let client = Scripted::default();
+		let reader = reader(&client, 8);
+
+		let concentrated = r#"{"pool":{"@type":"/osmosis.concentratedliquidity.v1beta1.Pool","id":"1",
+			"token0":"uosmo","token1":"uatom","current_sqrt_price":"1","current_tick":"0",
+			"current_tick_liquidity":"100","tick_spacing":"100","spread_factor":"0.001"}}"#;
+		client.push(&reader.pool_url(1), Ok(concentrated.to_string()));
+		let ticks = r#"{"liquidity":[{"liquidity_amount":"100","lower_tick":"-100","upper_tick":"100"}]}"#;
+		client.push(&reader.ticks_url(1), Ok(ticks.to_string()));
+		let PoolModel::Concentrated { ticks, .. } = reader.pool(1).unwrap().model else {
+			panic!("not a concentrated pool");
+		};
+		assert_eq!(ticks.len(), 2);
+
+		let stableswap = r#"{"pool":{"@type":"/osmosis.gamm.poolmodels.stableswap.v1beta1.Pool","id":"2",
+			"pool_params":{"swap_fee":"0.0005"},"pool_liquidity":[{"denom":"uusdc","amount":"1000"},
+			{"denom":"uusdt","amount":"1000"}],"scaling_factors":["1","1"]}}"#;
+		client.push(&reader.pool_url(2), Ok(stableswap.to_string()));
+		assert!(matches!(reader.pool(2).unwrap().model, PoolModel::Stableswap { .. }));
+		// no ticks query for the other pool types
+		assert_eq!(client.requests_to(&reader.ticks_url(2)), 0);
+	}
+
+	#[test]
+	fn folds_ranges_into_net_liquidity() {
+		let json = r#"{"liquidity":[
+			{"liquidity_amount":"100.5","lower_tick":"-200","upper_tick":"200"},
+			{"liquidity_amount":"50","lower_tick":"-200","upper_tick":"100"},
+			{"liquidity_amount":"25","lower_tick":"100","upper_tick":"300"},
+			{"liquidity_amount":"10","lower_tick":"300","upper_tick":"400"}
+		]}"#;
+		let unit = 10i128.pow(18);
+		assert_eq!(
+			parse_ticks(json).unwrap(),
+			vec![
+				Tick { index: -200, liquidity_net: 150 * unit + unit / 2 },
+				// the 50 leaving and the 25 entering
+				Tick { index: 100, liquidity_net: -25 * unit },
+				Tick { index: 200, liquidity_net: -100 * unit - unit / 2 },
+				// 25 out and 10 in
+				Tick { index: 300, liquidity_net: -15 * unit },
+				Tick { index: 400, liquidity_net: -10 * unit },
+			]
+		);
+
+		// ranges cancelling out leave no initialized tick
+		let json = r#"{"liquidity":[
+			{"liquidity_amount":"5","lower_tick":"0","upper_tick":"10"},
+			{"liquidity_amount":"5","lower_tick":"10","upper_tick":"20"}
+		]}"#;
+		let ticks = parse_ticks(json).unwrap();
+		assert_eq!(ticks.iter().map(|tick| tick.index).collect::<Vec<_>>(), vec![0, 20]);
+	}
+
+	#[test]
+	fn net_liquidity_overflow_is_an_error() {
+		// each range fits an i128 but their sum does not
+		let big = (i128::MAX / 10i128.pow(18)).to_string();
+		let json = format!(
+			r#"{{"liquidity":[{{"liquidity_amount":"{0}","lower_tick":"0","upper_tick":"10"}},
+			{{"liquidity_amount":"{0}","lower_tick":"0","upper_tick":"20"}}]}}"#,
+			big
+		);
+		assert_eq!(
+			parse_ticks(&json),
+			Err(ParseError::InvalidNumber { field: "liquidity_amount", value: big })
+		);
+	}
+}
@@ -0,0 +1,299 @@
+use serde::{Deserialize, Serialize};
+
+use crate::feeds::{
+	check_denom,
+	http::{encode_query, HttpClient},
+	parse_decimal, parse_float, FetchError, ParseError, PoolModel, PoolSnapshot,
+};
+
+// Dexscreener search ingestion
+//
+// `api.dexscreener.com/latest/dex/search` lists pairs of every chain; Osmosis
+// pairs carry the pool id as the first `-` separated part of `pairAddress`
+
+pub const DEXSCREENER_SEARCH_URL: &str = "https://api.dexscreener.com/latest/dex/search";
+pub const OSMOSIS_CHAIN_ID: &str = "osmosis";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResponse {
+	#[serde(default)]
+	pub schema_version: Option<String>,
+	/// `null` when nothing matches
+	#[serde(default)]
+	pub pairs: Option<Vec<Pair>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Pair {
+	pub chain_id: String,
+	pub dex_id: String,
+	#[serde(default)]
+	pub url: Option<String>,
+	pub pair_address: String,
+	pub base_token: PairToken,
+	pub quote_token: PairToken,
+	/// price of the base token in quote tokens
+	#[serde(default)]
+	pub price_native: Option<String>,
+	#[serde(default)]
+	pub price_usd: Option<String>,
+	#[serde(default)]
+	pub liquidity: Option<Liquidity>,
+	#[serde(default)]
+	pub pair_created_at: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PairToken {
+	/// the denom on Osmosis
+	pub address: String,
+	#[serde(default)]
+	pub name: Option<String>,
+	pub symbol: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Liquidity {
+	#[serde(default)]
+	pub usd: Option<f64>,
+	#[serde(default)]
+	pub base: Option<f64>,
+	#[serde(default)]
+	pub quote: Option<f64>,
+}
+
+/// Osmosis pairs of a search, split between the usable ones and those rejected
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Normalized {
+	pub snapshots: Vec<PoolSnapshot>,
+	/// pair address and the reason it was left out
+	pub rejected: Vec<(String, ParseError)>,
+}
+
+impl SearchResponse {
+	pub fn from_json(json: &str) -> Result<Self, ParseError> {
+		Ok(serde_json::from_str(json)?)
+	}
+
+	/// snapshots of the Osmosis pairs, the pairs of other chains being ignored
+	pub fn normalize(&self) -> Normalized {
+		let mut normalized = Normalized::default();
+		let pairs = self.pairs.iter().flatten().filter(|pair| pair.chain_id == OSMOSIS_CHAIN_ID);
+		for pair in pairs {
+			match pair.to_snapshot() {
+				Ok(snapshot) => normalized.snapshots.push(snapshot),
+				Err(e) => normalized.rejected.push((pair.pair_address.clone(), e)),
+			}
+		}
+		normalized
+	}
+}
+
+impl Pair {
+	/// pool id from a `"<id>-<base>-<quote>"` pair address
+	pub fn pool_id(&self) -> Result<u64, ParseError> {
+		self.pair_address
+			.split('-')
+			.next()
+			.and_then(|id| id.parse().ok())
+			.ok_or_else(|| ParseError::InvalidPairAddress(self.pair_address.clone()))
+	}
+
+	/// the pair as a snapshot without reserves
+	///
+	/// a missing `liquidity.usd` is left unknown, while a `priceNative` that is
+	/// not a number rejects the pair since the price is all Dexscreener adds
+	pub fn to_snapshot(&self) -> Result<PoolSnapshot, ParseError> {
+		let price = self
+			.price_native
+			.as_deref()
+			.map(|price| parse_decimal("priceNative", price))
+			.transpose()?;
+		let liquidity_usd = self
+			.liquidity
+			.as_ref()
+			.and_then(|liquidity| liquidity.usd)
+			.map(|usd| parse_float("liquidity.usd", usd))
+			.transpose()?;
+
+		Ok(PoolSnapshot {
+			pool_id: self.pool_id()?,
+			denoms: vec![check_denom(&self.base_token.address)?, check_denom(&self.quote_token.address)?],
+			fee: None,
+			model: PoolModel::Unknown,
+			liquidity_usd,
+			price,
+		})
+	}
+}
+
+/// search client over any `HttpClient`
+pub struct Dexscreener<C> {
+	client: C,
+	search_url: String,
+}
+
+impl<C: HttpClient> Dexscreener<C> {
+	pub fn new(client: C) -> Self {
+		Self::with_search_url(client, DEXSCREENER_SEARCH_URL)
+	}
+
+	pub fn with_search_url(client: C, search_url: &str) -> Self {
+		Dexscreener {
+			client,
+			search_url: search_url.to_string(),
+		}
+	}
+
+	/// URL queried for `query`, e.g. `"OSMO ATOM"`
+	pub fn search_url(&self, query: &str) -> String {
+		format!("{}?q={}", self.search_url, encode_query(query))
+	}
+
+	pub fn search(&self, query: &str) -> Result<SearchResponse, FetchError> {
+		let body = self.client.get(&self.search_url(query), &[])?;
+		Ok(SearchResponse::from_json(&body)?)
+	}
+
+	pub fn search_pools(&self, query: &str) -> Result<Normalized, FetchError> {
+		self.search(query).map(|response| response.normalize())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{feeds::http::FixtureClient, math::Decimal};
+
+	const ATOM: &str = "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2";
+
+	// `search?q=ATOM%20OSMO`, trimmed to a few pairs of each kind
+	const SEARCH: &str = r#"{
+		"schemaVersion": "1.0.0",
+		"pairs": [
+			{
+				"chainId": "osmosis",
+				"dexId": "osmosis",
+				"url": "https://dexscreener.com/osmosis/1-ibc_27394fb092d2eccd56123c74f36e4c1f926001ceada9ca97ea622b25f41e5eb2-uosmo",
+				"pairAddress": "1-ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2-uosmo",
+				"baseToken": {
+					"address": "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2",
+					"name": "Cosmos Hub",
+					"symbol": "ATOM"
+				},
+				"quoteToken": { "address": "uosmo", "name": "Osmosis", "symbol": "OSMO" },
+				"priceNative": "5.8451",
+				"priceUsd": "9.412",
+				"txns": { "h24": { "buys": 412, "sells": 388 } },
+				"volume": { "h24": 1203345.21 },
+				"liquidity": { "usd": 19712345.67, "base": 1066097, "quote": 6231442 },
+				"pairCreatedAt": 1623865140000
+			},
+			{
+				"chainId": "osmosis",
+				"dexId": "osmosis",
+				"pairAddress": "1135-uosmo-ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2",
+				"baseToken": { "address": "uosmo", "name": "Osmosis", "symbol": "OSMO" },
+				"quoteToken": {
+					"address": "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2",
+					"name": "Cosmos Hub",
+					"symbol": "ATOM"
+				},
+				"priceNative": "0.1710",
+				"liquidity": { "base": 8213342, "quote": 1404507 }
+			},
+			{
+				"chainId": "osmosis",
+				"dexId": "osmosis",
+				"pairAddress": "1400-uosmo-ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2",
+				"baseToken": { "address": "uosmo", "symbol": "OSMO" },
+				"quoteToken": { "address": "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2", "symbol": "ATOM" },
+				"priceNative": "N/A",
+				"liquidity": { "usd": 12.5 }
+			},
+			{
+				"chainId": "osmosis",
+				"dexId": "osmosis",
+				"pairAddress": "osmo10c8y69yylnlwrhu32ralf08ekladhfknfqrjsy9yqc9ml8mlxpqq2sttzk",
+				"baseToken": { "address": "uosmo", "symbol": "OSMO" },
+				"quoteToken": { "address": "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2", "symbol": "ATOM" },
+				"priceNative": "0.1712",
+				"liquidity": { "usd": 40211.3 }
+			},
+			{
+				"chainId": "ethereum",
+				"dexId": "uniswap",
+				"pairAddress": "0x98A19d4954B433Bd315335A05d7d6371D812A492",
+				"baseToken": { "address": "0x8D983cb9388EaC77af0474fA441C4815500Cb7BB", "name": "Cosmos", "symbol": "ATOM" },
+				"quoteToken": { "address": "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2", "name": "Wrapped Ether", "symbol": "WETH" },
+				"priceNative": "0.003102",
+				"liquidity": { "usd": 53912.4 }
+			},
+			{
+				"chainId": "injective",
+				"dexId": "dojoswap",
+				"pairAddress": "inj1h0mpv48ctcsmydymh2hnkal7hla5gl4gftemqv",
+				"baseToken": { "address": "ibc/C4CFF46FD6DE35CA4CF4CE031E643C8FDC9BA4B99AE598E9B0ED98FE3A2319F9", "symbol": "ATOM" },
+				"quoteToken": { "address": "inj", "symbol": "INJ" },
+				"priceNative": "not a price"
+			}
+		]
+	}"#;
+
+	fn client() -> Dexscreener<FixtureClient> {
+		let mut fixtures = FixtureClient::new();
+		fixtures.insert(&format!("{}?q=ATOM%20OSMO", DEXSCREENER_SEARCH_URL), SEARCH);
+		fixtures.insert(&format!("{}?q=NOTHING", DEXSCREENER_SEARCH_URL), r#"{"schemaVersion":"1.0.0","pairs":null}"#);
+		Dexscreener::new(fixtures)
+	}
+
+	#[test]
+	fn normalizes_the_osmosis_pairs_of_a_search() {
+		let normalized = client().search_pools("ATOM OSMO").unwrap();
+
+		// the ethereum and injective pairs are dropped without being reported
+		let ids: Vec<u64> = normalized.snapshots.iter().map(|snapshot| snapshot.pool_id).collect();
+		assert_eq!(ids, vec![1, 1135]);
+		assert_eq!(normalized.rejected.len(), 2);
+
+		let pool_1 = &normalized.snapshots[0];
+		assert_eq!(pool_1.denoms, vec![ATOM.to_string(), "uosmo".to_string()]);
+		assert_eq!(pool_1.price, Some("5.8451".parse().unwrap()));
+		assert_eq!(pool_1.liquidity_usd, Some("19712345.67".parse().unwrap()));
+		assert_eq!(pool_1.fee, None);
+		assert_eq!(pool_1.model, PoolModel::Unknown);
+
+		// reserves without a usd value leave the liquidity unknown
+		let pool_1135 = &normalized.snapshots[1];
+		assert_eq!(pool_1135.price, Some("0.171".parse::<Decimal>().unwrap()));
+		assert_eq!(pool_1135.liquidity_usd, None);
+	}
+
+	#[test]
+	fn rejects_bad_prices_and_pair_addresses() {
+		let normalized = client().search_pools("ATOM OSMO").unwrap();
+		assert_eq!(
+			normalized.rejected[0],
+			(
+				"1400-uosmo-ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2".to_string(),
+				ParseError::InvalidNumber { field: "priceNative", value: "N/A".to_string() }
+			)
+		);
+		let address = "osmo10c8y69yylnlwrhu32ralf08ekladhfknfqrjsy9yqc9ml8mlxpqq2sttzk".to_string();
+		assert_eq!(normalized.rejected[1], (address.clone(), ParseError::InvalidPairAddress(address)));
+	}
+
+	#[test]
+	fn empty_and_failed_searches() {
+		assert_eq!(client().search_pools("NOTHING").unwrap(), Normalized::default());
+		assert_eq!(
+			client().search_pools("ATOM").unwrap_err(),
+			FetchError::Status {
+				url: format!("{}?q=ATOM", DEXSCREENER_SEARCH_URL),
+				status: 404,
+			}
+		);
+	}
+}
@@ -0,0 +1,152 @@
+use std::{collections::HashMap, fs, io, path::Path, sync::Mutex};
+
+use crate::feeds::FetchError;
+
+// transport used by the feed clients
+//
+// clients only need a GET returning the body, so the real HTTP agent can be
+// swapped for canned responses loaded from fixture files, or recorded from a
+// live endpoint once and replayed afterwards
+
+pub trait HttpClient {
+	/// body of a successful GET of `url`
+	fn get(&self, url: &str, headers: &[(&str, &str)]) -> Result<String, FetchError>;
+}
+
+impl<C: HttpClient + ?Sized> HttpClient for &C {
+	fn get(&self, url: &str, headers: &[(&str, &str)]) -> Result<String, FetchError> {
+		(**self).get(url, headers)
+	}
+}
+
+/// blocking HTTP client
+///
+/// ureq is built without its rustls backend, whose zeroize requirement
+/// conflicts with the one solana-program pins, so HTTPS needs a connector
+/// from the binary, e.g. a `native_tls::TlsConnector`
+#[cfg(not(target_os = "solana"))]
+pub struct UreqClient {
+	agent: ureq::Agent,
+}
+
+#[cfg(not(target_os = "solana"))]
+impl UreqClient {
+	/// plain HTTP only, as for a local node or proxy
+	pub fn new(timeout: std::time::Duration) -> Self {
+		UreqClient {
+			agent: ureq::AgentBuilder::new().timeout(timeout).build(),
+		}
+	}
+
+	/// HTTP and HTTPS, the TLS handshake being done by `tls`
+	pub fn with_tls<T: ureq::TlsConnector + 'static>(timeout: std::time::Duration, tls: std::sync::Arc<T>) -> Self {
+		UreqClient {
+			agent: ureq::AgentBuilder::new().timeout(timeout).tls_connector(tls).build(),
+		}
+	}
+}
+
+#[cfg(not(target_os = "solana"))]
+impl Default for UreqClient {
+	fn default() -> Self {
+		UreqClient::new(std::time::Duration::from_secs(30))
+	}
+}
+
+#[cfg(not(target_os = "solana"))]
+impl HttpClient for UreqClient {
+	fn get(&self, url: &str, headers: &[(&str, &str)]) -> Result<String, FetchError> {
+		let request = headers
+			.iter()
+			.fold(self.agent.get(url), |request, (name, value)| request.set(name, value));
+		match request.call() {
+			Ok(response) => response.into_string().map_err(|e| FetchError::Transport(e.to_string())),
+			Err(ureq::Error::Status(status, _)) => Err(FetchError::Status {
+				url: url.to_string(),
+				status,
+			}),
+			Err(e) => Err(FetchError::Transport(e.to_string())),
+		}
+	}
+}
+
+/// canned responses keyed by URL, anything else answers 404
+#[derive(Default)]
+pub struct FixtureClient {
+	responses: HashMap<String, String>,
+}
+
+impl FixtureClient {
+	pub fn new() -> Self {
+		FixtureClient::default()
+	}
+
+	pub fn insert(&mut self, url: &str, body: &str) {
+		self.responses.insert(url.to_string(), body.to_string());
+	}
+
+	/// answer `url` with the content of the file at `path`
+	pub fn insert_file<P: AsRef<Path>>(&mut self, url: &str, path: P) -> io::Result<()> {
+		let body = fs::read_to_string(path)?;
+		self.responses.insert(url.to_string(), body);
+		Ok(())
+	}
+
+	/// responses saved by `RecordingClient::save`
+	pub fn from_recording<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+		let responses = serde_json::from_slice(&fs::read(path)?)?;
+		Ok(FixtureClient { responses })
+	}
+}
+
+impl HttpClient for FixtureClient {
+	fn get(&self, url: &str, _headers: &[(&str, &str)]) -> Result<String, FetchError> {
+		self.responses.get(url).cloned().ok_or_else(|| FetchError::Status {
+			url: url.to_string(),
+			status: 404,
+		})
+	}
+}
+
+/// passes requests to `inner` and keeps the successful responses for replay
+pub struct RecordingClient<C> {
+	inner: C,
+	responses: Mutex<HashMap<String, String>>,
+}
+
+impl<C: HttpClient> RecordingClient<C> {
+	pub fn new(inner: C) -> Self {
+		RecordingClient {
+			inner,
+			responses: Mutex::new(HashMap::new()),
+		}
+	}
+
+	/// write the recorded responses as a JSON map of URL to body
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+		let responses = self.responses.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+		fs::write(path, serde_json::to_vec_pretty(&*responses)?)
+	}
+}
+
+impl<C: HttpClient> HttpClient for RecordingClient<C> {
+	fn get(&self, url: &str, headers: &[(&str, &str)]) -> Result<String, FetchError> {
+		let body = self.inner.get(url, headers)?;
+		self.responses
+			.lock()
+			.unwrap_or_else(|poisoned| poisoned.into_inner())
+			.insert(url.to_string(), body.clone());
+		Ok(body)
+	}
+}
+
+/// percent-encode a query parameter value
+pub fn encode_query(value: &str) -> String {
+	value
+		.bytes()
+		.map(|byte| match byte {
+			b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+			_ => format!("%{:02X}", byte),
+		})
+		.collect()
+}
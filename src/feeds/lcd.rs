@@ -0,0 +1,420 @@
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+	amm::{stableswap::StableAsset, PoolAsset},
+	feeds::{check_denom, parse_decimal, parse_int, ParseError, PoolModel, PoolSnapshot},
+	math::Decimal,
+};
+
+// parser of the `/osmosis/gamm/v1beta1/pools/{id}` and
+// `/osmosis/poolmanager/v1beta1/pools/{id}` LCD responses
+//
+// the pool is picked by its `@type`; the fee is searched with the same
+// precedence as `getPoolFeeFromRpc` in fetchData.js
+
+pub const BALANCER_POOL_TYPE: &str = "/osmosis.gamm.v1beta1.Pool";
+pub const STABLESWAP_POOL_TYPE: &str = "/osmosis.gamm.poolmodels.stableswap.v1beta1.Pool";
+pub const CONCENTRATED_POOL_TYPE: &str = "/osmosis.concentratedliquidity.v1beta1.Pool";
+pub const COSMWASM_POOL_TYPE: &str = "/osmosis.cosmwasmpool.v1beta1.CosmWasmPool";
+
+/// parse the body of a pool query
+pub fn parse_pool_response(json: &str) -> Result<PoolSnapshot, ParseError> {
+	let response: Value = serde_json::from_str(json)?;
+	let pool = response.get("pool").ok_or(ParseError::MissingField("pool"))?;
+	parse_pool(pool)
+}
+
+/// parse a single `pool` object, as also found in the `pools` list of the all pools query
+pub fn parse_pool(pool: &Value) -> Result<PoolSnapshot, ParseError> {
+	let pool_type = pool
+		.get("@type")
+		.and_then(Value::as_str)
+		.ok_or(ParseError::MissingField("@type"))?;
+	let fee = resolve_fee(pool)?;
+
+	let (pool_id, denoms, model) = match pool_type {
+		BALANCER_POOL_TYPE => balancer(serde_json::from_value(pool.clone())?)?,
+		STABLESWAP_POOL_TYPE => stableswap(serde_json::from_value(pool.clone())?)?,
+		CONCENTRATED_POOL_TYPE => concentrated(serde_json::from_value(pool.clone())?)?,
+		COSMWASM_POOL_TYPE => cosmwasm(serde_json::from_value(pool.clone())?)?,
+		other => return Err(ParseError::UnsupportedPoolType(other.to_string())),
+	};
+
+	// only CosmWasm pools keep their fee inside the contract
+	if fee.is_none() && !matches!(model, PoolModel::CosmWasm { .. }) {
+		return Err(ParseError::MissingFee);
+	}
+
+	Ok(PoolSnapshot {
+		pool_id,
+		denoms,
+		fee,
+		model,
+		liquidity_usd: None,
+		price: None,
+	})
+}
+
+/// fee of the pool, the first of `spread_factor`, `pool_params.spread`,
+/// `spread`, `pool_params.swap_fee` and `swap_fee` that is set
+pub fn resolve_fee(pool: &Value) -> Result<Option<Decimal>, ParseError> {
+	let params = pool.get("pool_params");
+	let candidates = [
+		("spread_factor", pool.get("spread_factor")),
+		("pool_params.spread", params.and_then(|params| params.get("spread"))),
+		("spread", pool.get("spread")),
+		("pool_params.swap_fee", params.and_then(|params| params.get("swap_fee"))),
+		("swap_fee", pool.get("swap_fee")),
+	];
+	// empty strings and nulls count as unset, like the falsy checks of the JS
+	let found = candidates.into_iter().find_map(|(field, value)| {
+		let value = value.and_then(Value::as_str).filter(|value| !value.is_empty())?;
+		Some((field, value))
+	});
+	found.map(|(field, value)| parse_decimal(field, value)).transpose()
+}
+
+// raw shapes of each pool type, only the fields used here
+
+#[derive(Deserialize)]
+struct Coin {
+	denom: String,
+	amount: String,
+}
+
+#[derive(Deserialize)]
+struct BalancerAsset {
+	token: Coin,
+	weight: String,
+}
+
+#[derive(Deserialize)]
+struct BalancerJson {
+	id: String,
+	pool_assets: Vec<BalancerAsset>,
+}
+
+#[derive(Deserialize)]
+struct StableswapJson {
+	id: String,
+	pool_liquidity: Vec<Coin>,
+	scaling_factors: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ConcentratedJson {
+	id: String,
+	token0: String,
+	token1: String,
+	current_sqrt_price: String,
+	current_tick: String,
+	current_tick_liquidity: String,
+	tick_spacing: String,
+}
+
+#[derive(Deserialize)]
+struct CosmWasmJson {
+	pool_id: String,
+	contract_address: String,
+	code_id: String,
+}
+
+type Parsed = (u64, Vec<String>, PoolModel);
+
+fn balancer(pool: BalancerJson) -> Result<Parsed, ParseError> {
+	let assets = pool
+		.pool_assets
+		.into_iter()
+		.map(|asset| {
+			Ok(PoolAsset {
+				denom: check_denom(&asset.token.denom)?,
+				reserve: parse_int("pool_assets.token.amount", &asset.token.amount)?,
+				weight: parse_int("pool_assets.weight", &asset.weight)?,
+			})
+		})
+		.collect::<Result<Vec<_>, ParseError>>()?;
+	let denoms = assets.iter().map(|asset| asset.denom.clone()).collect();
+	Ok((parse_int("id", &pool.id)?, denoms, PoolModel::Balancer { assets }))
+}
+
+fn stableswap(pool: StableswapJson) -> Result<Parsed, ParseError> {
+	// a pool created without scaling factors uses 1 for every asset
+	let scaling_factors = match pool.scaling_factors.len() {
+		0 => vec![1; pool.pool_liquidity.len()],
+		n if n == pool.pool_liquidity.len() => pool
+			.scaling_factors
+			.iter()
+			.map(|factor| parse_int("scaling_factors", factor))
+			.collect::<Result<Vec<u128>, _>>()?,
+		found => {
+			return Err(ParseError::LengthMismatch {
+				field: "scaling_factors",
+				expected: pool.pool_liquidity.len(),
+				found,
+			})
+		}
+	};
+	let assets = pool
+		.pool_liquidity
+		.into_iter()
+		.zip(scaling_factors)
+		.map(|(coin, scaling_factor)| {
+			Ok(StableAsset {
+				denom: check_denom(&coin.denom)?,
+				reserve: parse_int("pool_liquidity.amount", &coin.amount)?,
+				scaling_factor,
+			})
+		})
+		.collect::<Result<Vec<_>, ParseError>>()?;
+	let denoms = assets.iter().map(|asset| asset.denom.clone()).collect();
+	Ok((parse_int("id", &pool.id)?, denoms, PoolModel::Stableswap { assets }))
+}
+
+fn concentrated(pool: ConcentratedJson) -> Result<Parsed, ParseError> {
+	let denoms = vec![check_denom(&pool.token0)?, check_denom(&pool.token1)?];
+	let model = PoolModel::Concentrated {
+		current_sqrt_price: parse_decimal("current_sqrt_price", &pool.current_sqrt_price)?,
+		current_tick: parse_int("current_tick", &pool.current_tick)?,
+		liquidity: parse_decimal("current_tick_liquidity", &pool.current_tick_liquidity)?,
+		tick_spacing: parse_int("tick_spacing", &pool.tick_spacing)?,
+		ticks: Vec::new(),
+	};
+	Ok((parse_int("id", &pool.id)?, denoms, model))
+}
+
+fn cosmwasm(pool: CosmWasmJson) -> Result<Parsed, ParseError> {
+	// the traded denoms live in the contract state, not in the pool record
+	let model = PoolModel::CosmWasm {
+		contract_address: pool.contract_address,
+		code_id: parse_int("code_id", &pool.code_id)?,
+	};
+	Ok((parse_int("pool_id", &pool.pool_id)?, Vec::new(), model))
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::json;
+
+	use super::*;
+	use crate::{
+		amm::{concentrated::Tick, PoolSet},
+		error::FeanorError,
+	};
+
+	const ATOM: &str = "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2";
+	const USDC: &str = "ibc/498A0751C798A0D9A389AA3691123DADA57DAA4FE165D5C75894505B876BA6E4";
+	const AXL_USDC: &str = "ibc/D189335C6E4A68B513C10AB227BF1C1D38C746766278BA3EEB4FB14124F1D858";
+
+	// bodies of `/osmosis/poolmanager/v1beta1/pools/{id}` as served by lcd.osmosis.zone
+
+	const BALANCER: &str = r#"{
+		"pool": {
+			"@type": "/osmosis.gamm.v1beta1.Pool",
+			"address": "osmo1mw0ac6rwlp5r8wapwk3zs6g29h8fcscxqakdzw9emkne6c8wjp9q0t3v8t",
+			"id": "1",
+			"pool_params": {
+				"swap_fee": "0.002000000000000000",
+				"exit_fee": "0.000000000000000000",
+				"smooth_weight_change_params": null
+			},
+			"future_pool_governor": "24h",
+			"total_shares": { "denom": "gamm/pool/1", "amount": "68705408290810473783205" },
+			"pool_assets": [
+				{
+					"token": {
+						"denom": "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2",
+						"amount": "1066097184357"
+					},
+					"weight": "536870912000000"
+				},
+				{ "token": { "denom": "uosmo", "amount": "6231442117285" }, "weight": "536870912000000" }
+			],
+			"total_weight": "1073741824000000"
+		}
+	}"#;
+
+	const STABLESWAP: &str = r#"{
+		"pool": {
+			"@type": "/osmosis.gamm.poolmodels.stableswap.v1beta1.Pool",
+			"address": "osmo1vldjwmr2ymnmhhjr0glfxsk4pknd9l7zqt6rkes9esr2t9wyh0js6yzt3d",
+			"id": "1246",
+			"pool_params": { "swap_fee": "0.000500000000000000", "exit_fee": "0.000000000000000000" },
+			"future_pool_governor": "osmo1z3vrtmj35eqf8axy7ka9dnhgwhf6ez2wh9vnle",
+			"total_shares": { "denom": "gamm/pool/1246", "amount": "1198304817418225702163411" },
+			"pool_liquidity": [
+				{
+					"denom": "ibc/498A0751C798A0D9A389AA3691123DADA57DAA4FE165D5C75894505B876BA6E4",
+					"amount": "629316221538"
+				},
+				{
+					"denom": "ibc/D189335C6E4A68B513C10AB227BF1C1D38C746766278BA3EEB4FB14124F1D858",
+					"amount": "569119883307"
+				}
+			],
+			"scaling_factors": ["1", "1"],
+			"scaling_factor_controller": "osmo1k8c2m5cn322akk5wy8lpt87dd2f4yh9afcd7af"
+		}
+	}"#;
+
+	const CONCENTRATED: &str = r#"{
+		"pool": {
+			"@type": "/osmosis.concentratedliquidity.v1beta1.Pool",
+			"address": "osmo1jlslxrxvxqgfxxwwj8yxhxf2rn8dkfgaphhz4aumd3ycxwsjdt0q36djxr",
+			"incentives_address": "osmo1ecm6wsyhk8g6e4ewhzvrrwqk7lq8mrenm5dm9ptwwfzj7tyqmnpsktmw2n",
+			"spread_rewards_address": "osmo18g3sd3u7ynf3x0dgzf6ynpvyjqz4xrq3yxqzw4xc8fxmgkc7s0qs9adclx",
+			"id": "1135",
+			"current_tick_liquidity": "1517804917557.928810218617716447",
+			"token0": "uosmo",
+			"token1": "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2",
+			"current_sqrt_price": "0.415232178612916428569497811637826913",
+			"current_tick": "-8275823",
+			"tick_spacing": "100",
+			"exponent_at_price_one": "-6",
+			"spread_factor": "0.002000000000000000",
+			"last_liquidity_update": "2024-02-26T14:02:11.118312487Z"
+		}
+	}"#;
+
+	const COSMWASM: &str = r#"{
+		"pool": {
+			"@type": "/osmosis.cosmwasmpool.v1beta1.CosmWasmPool",
+			"contract_address": "osmo10c8y69yylnlwrhu32ralf08ekladhfknfqrjsy9yqc9ml8mlxpqq2sttzk",
+			"pool_id": "1463",
+			"code_id": "572",
+			"instantiate_msg": "eyJwb29sX2Fzc2V0X2Rlbm9tcyI6WyJ1b3NtbyJdfQ=="
+		}
+	}"#;
+
+	#[test]
+	fn parses_balancer_pools() {
+		let snapshot = parse_pool_response(BALANCER).unwrap();
+		assert_eq!(snapshot.pool_id, 1);
+		assert_eq!(snapshot.denoms, vec![ATOM.to_string(), "uosmo".to_string()]);
+		assert_eq!(snapshot.fee, Some(Decimal::bps(20)));
+		let PoolModel::Balancer { assets } = &snapshot.model else {
+			panic!("not a balancer pool: {:?}", snapshot.model);
+		};
+		assert_eq!(assets[0].reserve, 1_066_097_184_357);
+		assert_eq!(assets[1].weight, 536_870_912_000_000);
+		snapshot.insert_into(&mut PoolSet::new()).unwrap();
+	}
+
+	#[test]
+	fn parses_stableswap_pools_but_keeps_them_out_of_routing() {
+		let snapshot = parse_pool_response(STABLESWAP).unwrap();
+		assert_eq!(snapshot.pool_id, 1246);
+		assert_eq!(snapshot.denoms, vec![USDC.to_string(), AXL_USDC.to_string()]);
+		assert_eq!(snapshot.fee, Some(Decimal::bps(5)));
+		let PoolModel::Stableswap { assets } = &snapshot.model else {
+			panic!("not a stableswap pool: {:?}", snapshot.model);
+		};
+		assert_eq!(assets[1].reserve, 569_119_883_307);
+		assert_eq!(assets[1].scaling_factor, 1);
+		// the Osmosis CFMM is not the Curve invariant of the amm module
+		assert_eq!(snapshot.insert_into(&mut PoolSet::new()), Err(FeanorError::InvalidPool));
+	}
+
+	#[test]
+	fn parses_concentrated_pools() {
+		let snapshot = parse_pool_response(CONCENTRATED).unwrap();
+		assert_eq!(snapshot.pool_id, 1135);
+		assert_eq!(snapshot.denoms, vec!["uosmo".to_string(), ATOM.to_string()]);
+		assert_eq!(snapshot.fee, Some(Decimal::bps(20)));
+		assert_eq!(
+			snapshot.model,
+			PoolModel::Concentrated {
+				// the 36 digit sqrt price is truncated to 18
+				current_sqrt_price: "0.415232178612916428".parse().unwrap(),
+				current_tick: -8_275_823,
+				liquidity: "1517804917557.928810218617716447".parse().unwrap(),
+				tick_spacing: 100,
+				ticks: Vec::<Tick>::new(),
+			}
+		);
+	}
+
+	#[test]
+	fn parses_cosmwasm_pools_without_a_fee() {
+		let snapshot = parse_pool_response(COSMWASM).unwrap();
+		assert_eq!(snapshot.pool_id, 1463);
+		assert!(snapshot.denoms.is_empty());
+		assert_eq!(snapshot.fee, None);
+		assert_eq!(
+			snapshot.model,
+			PoolModel::CosmWasm {
+				contract_address: "osmo10c8y69yylnlwrhu32ralf08ekladhfknfqrjsy9yqc9ml8mlxpqq2sttzk".to_string(),
+				code_id: 572,
+			}
+		);
+	}
+
+	#[test]
+	fn fee_precedence() {
+		let all = json!({
+			"spread_factor": "0.001",
+			"pool_params": { "spread": "0.002", "swap_fee": "0.004" },
+			"spread": "0.003",
+			"swap_fee": "0.005"
+		});
+		assert_eq!(resolve_fee(&all), Ok(Some(Decimal::bps(10))));
+
+		let params_spread = json!({
+			"pool_params": { "spread": "0.002", "swap_fee": "0.004" },
+			"spread": "0.003",
+			"swap_fee": "0.005"
+		});
+		assert_eq!(resolve_fee(&params_spread), Ok(Some(Decimal::bps(20))));
+
+		let spread = json!({ "pool_params": { "swap_fee": "0.004" }, "spread": "0.003", "swap_fee": "0.005" });
+		assert_eq!(resolve_fee(&spread), Ok(Some(Decimal::bps(30))));
+
+		let params_swap_fee = json!({ "pool_params": { "swap_fee": "0.004" }, "swap_fee": "0.005" });
+		assert_eq!(resolve_fee(&params_swap_fee), Ok(Some(Decimal::bps(40))));
+
+		let swap_fee = json!({ "swap_fee": "0.005" });
+		assert_eq!(resolve_fee(&swap_fee), Ok(Some(Decimal::bps(50))));
+
+		// empty strings and nulls fall through like the JS falsy checks
+		let unset = json!({ "spread_factor": "", "pool_params": { "spread": null }, "swap_fee": "0.005" });
+		assert_eq!(resolve_fee(&unset), Ok(Some(Decimal::bps(50))));
+
+		assert_eq!(resolve_fee(&json!({})), Ok(None));
+		assert!(matches!(
+			resolve_fee(&json!({ "swap_fee": "0,3" })),
+			Err(ParseError::InvalidNumber { field: "swap_fee", .. })
+		));
+	}
+
+	#[test]
+	fn missing_fee_is_an_error_outside_cosmwasm_pools() {
+		let mut pool: Value = serde_json::from_str(BALANCER).unwrap();
+		pool["pool"]["pool_params"]["swap_fee"] = json!("");
+		assert_eq!(parse_pool(&pool["pool"]), Err(ParseError::MissingFee));
+
+		let mut pool: Value = serde_json::from_str(CONCENTRATED).unwrap();
+		pool["pool"].as_object_mut().unwrap().remove("spread_factor");
+		assert_eq!(parse_pool(&pool["pool"]), Err(ParseError::MissingFee));
+	}
+
+	#[test]
+	fn rejects_unknown_pool_types_and_bad_fields() {
+		let unknown = json!({ "@type": "/osmosis.gamm.v2.Pool", "swap_fee": "0.001" });
+		assert_eq!(parse_pool(&unknown), Err(ParseError::UnsupportedPoolType("/osmosis.gamm.v2.Pool".to_string())));
+		assert_eq!(parse_pool_response("{}"), Err(ParseError::MissingField("pool")));
+
+		let mut pool: Value = serde_json::from_str(STABLESWAP).unwrap();
+		pool["pool"]["pool_liquidity"][0]["denom"] = json!("ibc/XYZ");
+		assert_eq!(parse_pool(&pool["pool"]), Err(ParseError::InvalidDenom("ibc/XYZ".to_string())));
+		pool["pool"]["pool_liquidity"][0]["denom"] = json!(USDC);
+		pool["pool"]["scaling_factors"] = json!(["1"]);
+		assert_eq!(
+			parse_pool(&pool["pool"]),
+			Err(ParseError::LengthMismatch {
+				field: "scaling_factors",
+				expected: 2,
+				found: 1,
+			})
+		);
+	}
+}
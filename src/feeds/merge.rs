@@ -0,0 +1,450 @@
+use std::{cmp::Reverse, collections::BTreeMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	feeds::{PoolModel, PoolSnapshot},
+	math::Decimal,
+};
+
+// merge of the snapshots several sources give for the same pools
+//
+// unlike the `unifiedPoolsMap` of displayGrid.js, where the last source
+// written wins, every field keeps the source and time it came from, the policy
+// decides which source is trusted first, and disagreements beyond a tolerance
+// are reported instead of being silently overwritten
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+	Chain,
+	Numia,
+	Dexscreener,
+}
+
+/// a value and where it comes from
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct Field<T> {
+	pub value: T,
+	pub source: Source,
+	/// unix seconds of the observation
+	pub observed_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictField {
+	Denoms,
+	Price,
+	LiquidityUsd,
+	Fee,
+}
+
+/// sources disagreeing on a field by more than the tolerance
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct Conflict {
+	pub field: ConflictField,
+	/// source whose value was kept
+	pub chosen: Source,
+	/// sources rejected and their values, as strings to cover denom lists
+	pub rejected: Vec<(Source, String)>,
+	/// largest relative gap to the kept value, zero for denoms
+	pub deviation: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct MergedPool {
+	pub pool_id: u64,
+	pub denoms: Field<Vec<String>>,
+	pub fee: Option<Field<Decimal>>,
+	/// the most detailed model known, `Unknown` only when no source has one
+	pub model: Field<PoolModel>,
+	pub liquidity_usd: Option<Field<Decimal>>,
+	/// units of `denoms[1]` per unit of `denoms[0]`
+	pub price: Option<Field<Decimal>>,
+	pub conflicts: Vec<Conflict>,
+}
+
+impl MergedPool {
+	pub fn has_conflicts(&self) -> bool {
+		!self.conflicts.is_empty()
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct MergePolicy {
+	/// sources from the most to the least trusted, unlisted ones coming last
+	pub priority: Vec<Source>,
+	/// relative gap between prices above which sources conflict
+	pub price_tolerance: Decimal,
+	pub liquidity_tolerance: Decimal,
+	pub fee_tolerance: Decimal,
+}
+
+impl Default for MergePolicy {
+	fn default() -> Self {
+		MergePolicy {
+			priority: vec![Source::Chain, Source::Numia, Source::Dexscreener],
+			price_tolerance: Decimal::percent(1),
+			liquidity_tolerance: Decimal::percent(5),
+			fee_tolerance: Decimal::ZERO,
+		}
+	}
+}
+
+impl MergePolicy {
+	fn rank(&self, source: Source) -> usize {
+		self.priority
+			.iter()
+			.position(|trusted| *trusted == source)
+			.unwrap_or(self.priority.len())
+	}
+}
+
+struct Observation {
+	source: Source,
+	observed_at: u64,
+	snapshot: PoolSnapshot,
+}
+
+#[derive(Default)]
+pub struct PoolMerger {
+	policy: MergePolicy,
+	observations: BTreeMap<u64, Vec<Observation>>,
+}
+
+impl PoolMerger {
+	pub fn new(policy: MergePolicy) -> Self {
+		PoolMerger {
+			policy,
+			observations: BTreeMap::new(),
+		}
+	}
+
+	pub fn add(&mut self, source: Source, observed_at: u64, snapshot: PoolSnapshot) {
+		self.observations.entry(snapshot.pool_id).or_default().push(Observation {
+			source,
+			observed_at,
+			snapshot,
+		});
+	}
+
+	pub fn extend<I: IntoIterator<Item = PoolSnapshot>>(&mut self, source: Source, observed_at: u64, snapshots: I) {
+		for snapshot in snapshots {
+			self.add(source, observed_at, snapshot);
+		}
+	}
+
+	/// merged pools, sorted by pool id
+	pub fn merge(&self) -> Vec<MergedPool> {
+		self.observations
+			.iter()
+			.map(|(pool_id, observations)| self.merge_pool(*pool_id, observations))
+			.collect()
+	}
+
+	fn merge_pool(&self, pool_id: u64, observations: &[Observation]) -> MergedPool {
+		// most trusted first, then newest first within a source
+		let mut ordered: Vec<&Observation> = observations.iter().collect();
+		ordered.sort_by_key(|observation| {
+			(self.policy.rank(observation.source), Reverse(observation.observed_at))
+		});
+		let reference = ordered[0];
+		// CosmWasm pools keep their denoms in the contract, an empty list is
+		// unknown and the denoms come from the first source that has them
+		let denoms_reference = ordered
+			.iter()
+			.find(|observation| !observation.snapshot.denoms.is_empty())
+			.copied()
+			.unwrap_or(reference);
+		let denoms = &denoms_reference.snapshot.denoms;
+		let mut conflicts = Vec::new();
+
+		// sources listing other denoms describe something else, their values are left out
+		let mut same_pool = Vec::with_capacity(ordered.len());
+		let mut rejected = Vec::new();
+		for observation in ordered {
+			match orientation(denoms, &observation.snapshot.denoms) {
+				Some(orientation) => same_pool.push((observation, orientation)),
+				None => rejected.push((observation.source, observation.snapshot.denoms.join(","))),
+			}
+		}
+		if !rejected.is_empty() {
+			conflicts.push(Conflict {
+				field: ConflictField::Denoms,
+				chosen: denoms_reference.source,
+				rejected,
+				deviation: Decimal::ZERO,
+			});
+		}
+
+		let prices = same_pool.iter().filter_map(|(observation, orientation)| {
+			let price = observation.snapshot.price?;
+			// a price quoted the other way round is inverted, a zero one is unusable
+			let price = match orientation {
+				Orientation::Same => price,
+				Orientation::Reversed => price.inv().ok()?,
+				Orientation::Unpriced => return None,
+			};
+			Some(field(observation, price))
+		});
+		let price = resolve(ConflictField::Price, self.policy.price_tolerance, prices, &mut conflicts);
+
+		let liquidities = same_pool
+			.iter()
+			.filter_map(|(observation, _)| Some(field(observation, observation.snapshot.liquidity_usd?)));
+		let liquidity_usd = resolve(
+			ConflictField::LiquidityUsd,
+			self.policy.liquidity_tolerance,
+			liquidities,
+			&mut conflicts,
+		);
+
+		let fees = same_pool
+			.iter()
+			.filter_map(|(observation, _)| Some(field(observation, observation.snapshot.fee?)));
+		let fee = resolve(ConflictField::Fee, self.policy.fee_tolerance, fees, &mut conflicts);
+
+		let model = same_pool
+			.iter()
+			.map(|(observation, _)| *observation)
+			.find(|observation| observation.snapshot.model != PoolModel::Unknown)
+			.unwrap_or(reference);
+
+		MergedPool {
+			pool_id,
+			denoms: field(denoms_reference, denoms.clone()),
+			fee,
+			model: field(model, model.snapshot.model.clone()),
+			liquidity_usd,
+			price,
+			conflicts,
+		}
+	}
+}
+
+/// value of the most trusted candidate, with a conflict if another one is too far from it
+fn resolve<I: Iterator<Item = Field<Decimal>>>(
+	conflict_field: ConflictField,
+	tolerance: Decimal,
+	mut candidates: I,
+	conflicts: &mut Vec<Conflict>,
+	) -> Option<Field<Decimal>> {
+	let chosen = candidates.next()?;
+
+	let mut rejected = Vec::new();
+	let mut deviation = Decimal::ZERO;
+	for other in candidates {
+		let gap = relative_gap(chosen.value, other.value);
+		if gap > tolerance {
+			deviation = deviation.max(gap);
+			rejected.push((other.source, other.value.to_string()));
+		}
+	}
+	if !rejected.is_empty() {
+		conflicts.push(Conflict {
+			field: conflict_field,
+			chosen: chosen.source,
+			rejected,
+			deviation,
+		});
+	}
+	Some(chosen)
+}
+
+fn field<T>(observation: &Observation, value: T) -> Field<T> {
+	Field {
+		value,
+		source: observation.source,
+		observed_at: observation.observed_at,
+	}
+}
+
+/// how a source lists the denoms of a pool against the reference list
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Orientation {
+	/// same first two denoms, its price compares as is
+	Same,
+	/// first two denoms swapped, its price is inverted
+	Reversed,
+	/// its price is on another pair of a multi-asset pool or on unknown denoms
+	Unpriced,
+}
+
+/// how `other` lists the pool of `reference`, `None` for another pool
+///
+/// an aggregator lists a pair of a multi-asset pool, so one list containing
+/// the other is the same pool; an empty list is unknown and matches any
+fn orientation(reference: &[String], other: &[String]) -> Option<Orientation> {
+	if reference.is_empty() || other.is_empty() {
+		let orientation = if reference == other { Orientation::Same } else { Orientation::Unpriced };
+		return Some(orientation);
+	}
+	let contains = |outer: &[String], inner: &[String]| inner.iter().all(|denom| outer.contains(denom));
+	if !contains(reference, other) && !contains(other, reference) {
+		return None;
+	}
+	match (reference, other) {
+		([a, b, ..], [c, d, ..]) if a == c && b == d => Some(Orientation::Same),
+		([a, b, ..], [c, d, ..]) if a == d && b == c => Some(Orientation::Reversed),
+		_ => Some(Orientation::Unpriced),
+	}
+}
+
+/// `|other - chosen| / chosen`, saturating, a zero chosen value being infinitely far from any other
+fn relative_gap(chosen: Decimal, other: Decimal) -> Decimal {
+	let gap = other.max(chosen).checked_sub(other.min(chosen)).unwrap_or(Decimal::ZERO);
+	if gap.is_zero() {
+		return Decimal::ZERO;
+	}
+	gap.checked_div(chosen).unwrap_or(Decimal::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const ATOM: &str = "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2";
+	const USDC: &str = "ibc/498A0751C798A0D9A389AA3691123DADA57DAA4FE165D5C75894505B876BA6E4";
+
+	fn dec(value: &str) -> Decimal {
+		value.parse().unwrap()
+	}
+
+	fn snapshot(pool_id: u64, denoms: &[&str], price: Option<&str>) -> PoolSnapshot {
+		PoolSnapshot {
+			pool_id,
+			denoms: denoms.iter().map(|denom| denom.to_string()).collect(),
+			fee: None,
+			model: PoolModel::Unknown,
+			liquidity_usd: None,
+			price: price.map(dec),
+		}
+	}
+
+	fn merge_one(observations: Vec<(Source, u64, PoolSnapshot)>) -> MergedPool {
+		let mut merger = PoolMerger::default();
+		for (source, observed_at, snapshot) in observations {
+			merger.add(source, observed_at, snapshot);
+		}
+		let mut merged = merger.merge();
+		assert_eq!(merged.len(), 1);
+		merged.remove(0)
+	}
+
+	#[test]
+	fn inverts_prices_quoted_the_other_way_round() {
+		let merged = merge_one(vec![
+			(Source::Dexscreener, 10, snapshot(1, &[ATOM, "uosmo"], Some("0.5"))),
+			(Source::Numia, 10, snapshot(1, &["uosmo", ATOM], Some("2"))),
+		]);
+		assert_eq!(merged.denoms.value, vec!["uosmo".to_string(), ATOM.to_string()]);
+		assert_eq!(merged.denoms.source, Source::Numia);
+		assert_eq!(merged.price.as_ref().unwrap().value, dec("2"));
+		assert!(!merged.has_conflicts());
+	}
+
+	#[test]
+	fn trusts_the_priority_then_the_newest_observation() {
+		let merged = merge_one(vec![
+			(Source::Numia, 10, snapshot(1, &["uosmo", ATOM], Some("2"))),
+			(Source::Numia, 20, snapshot(1, &["uosmo", ATOM], Some("2.01"))),
+			(Source::Dexscreener, 30, snapshot(1, &["uosmo", ATOM], Some("2.5"))),
+		]);
+		let price = merged.price.unwrap();
+		assert_eq!((price.value, price.source, price.observed_at), (dec("2.01"), Source::Numia, 20));
+		// 0.5% off the kept price is within the 1% tolerance, 24% is not
+		assert_eq!(
+			merged.conflicts,
+			vec![Conflict {
+				field: ConflictField::Price,
+				chosen: Source::Numia,
+				rejected: vec![(Source::Dexscreener, "2.5".to_string())],
+				deviation: dec("0.49").checked_div(dec("2.01")).unwrap(),
+			}]
+		);
+	}
+
+	#[test]
+	fn takes_the_denoms_of_cosmwasm_pools_from_the_aggregators() {
+		let mut chain = snapshot(1463, &[], None);
+		chain.model = PoolModel::CosmWasm {
+			contract_address: "osmo10c8y69yylnlwrhu32ralf08ekladhfknfqrjsy9yqc9ml8mlxpqq2sttzk".to_string(),
+			code_id: 572,
+		};
+		let merged = merge_one(vec![
+			(Source::Chain, 10, chain.clone()),
+			(Source::Numia, 10, snapshot(1463, &["uosmo", USDC], Some("0.75"))),
+			(Source::Dexscreener, 10, snapshot(1463, &[USDC, "uosmo"], Some("1.333333333333333333"))),
+		]);
+		assert_eq!(merged.denoms.value, vec!["uosmo".to_string(), USDC.to_string()]);
+		assert_eq!(merged.denoms.source, Source::Numia);
+		assert_eq!(merged.model.value, chain.model);
+		assert_eq!(merged.model.source, Source::Chain);
+		assert_eq!(merged.price.as_ref().unwrap().value, dec("0.75"));
+		assert!(!merged.has_conflicts());
+	}
+
+	#[test]
+	fn matches_pairs_of_multi_asset_pools() {
+		let mut chain = snapshot(5, &["uosmo", ATOM, USDC], None);
+		chain.fee = Some(Decimal::bps(20));
+		let mut numia = snapshot(5, &[ATOM, "uosmo"], Some("0.5"));
+		numia.liquidity_usd = Some(dec("1000"));
+		// another pair of the pool, its price says nothing of uosmo/atom
+		let mut dexscreener = snapshot(5, &[USDC, "uosmo"], Some("100"));
+		dexscreener.liquidity_usd = Some(dec("1200"));
+
+		let merged = merge_one(vec![
+			(Source::Chain, 10, chain),
+			(Source::Numia, 10, numia),
+			(Source::Dexscreener, 10, dexscreener),
+		]);
+		assert_eq!(merged.denoms.value.len(), 3);
+		assert_eq!(merged.denoms.source, Source::Chain);
+		assert_eq!(merged.fee.as_ref().unwrap().value, Decimal::bps(20));
+		let price = merged.price.unwrap();
+		assert_eq!((price.value, price.source), (dec("2"), Source::Numia));
+		// the third source is still the same pool, its liquidity is compared
+		assert_eq!(merged.conflicts.len(), 1);
+		assert_eq!(merged.conflicts[0].field, ConflictField::LiquidityUsd);
+		assert_eq!(
+			merged.conflicts[0].rejected,
+			vec![(Source::Dexscreener, "1200".to_string())]
+		);
+	}
+
+	#[test]
+	fn leaves_out_sources_describing_another_pool() {
+		let mut other = snapshot(1, &["uosmo", USDC], Some("0.75"));
+		other.liquidity_usd = Some(dec("5"));
+		let merged = merge_one(vec![
+			(Source::Numia, 10, snapshot(1, &["uosmo", ATOM], Some("2"))),
+			(Source::Dexscreener, 10, other),
+		]);
+		assert_eq!(merged.price.as_ref().unwrap().value, dec("2"));
+		assert_eq!(merged.liquidity_usd, None);
+		assert_eq!(
+			merged.conflicts,
+			vec![Conflict {
+				field: ConflictField::Denoms,
+				chosen: Source::Numia,
+				rejected: vec![(Source::Dexscreener, format!("uosmo,{}", USDC))],
+				deviation: Decimal::ZERO,
+			}]
+		);
+	}
+
+	#[test]
+	fn orientation_of_denom_lists() {
+		let list = |denoms: &[&str]| denoms.iter().map(|denom| denom.to_string()).collect::<Vec<_>>();
+		let pool = list(&["a", "b", "c"]);
+		assert_eq!(orientation(&pool, &list(&["a", "b"])), Some(Orientation::Same));
+		assert_eq!(orientation(&pool, &list(&["b", "a"])), Some(Orientation::Reversed));
+		assert_eq!(orientation(&pool, &list(&["c", "a"])), Some(Orientation::Unpriced));
+		assert_eq!(orientation(&pool, &list(&[])), Some(Orientation::Unpriced));
+		assert_eq!(orientation(&list(&["b", "a"]), &pool), Some(Orientation::Reversed));
+		assert_eq!(orientation(&pool, &list(&["a", "d"])), None);
+		assert_eq!(orientation(&list(&[]), &list(&[])), Some(Orientation::Same));
+	}
+}
@@ -0,0 +1,191 @@
+#[cfg(not(target_os = "solana"))]
+pub mod chain;
+pub mod dexscreener;
+pub mod http;
+pub mod lcd;
+pub mod merge;
+#[cfg(not(target_os = "solana"))]
+pub mod numia;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+	amm::{
+		concentrated::Tick, stableswap::StableAsset, ConcentratedPool, ConstantProduct, PoolAsset, PoolSet,
+		WeightedPool,
+	},
+	denom::Denom,
+	error::FeanorError,
+	math::Decimal,
+};
+
+// pool data from the chain and the market data aggregators
+//
+// every source is normalized to a `PoolSnapshot`; what a source does not
+// provide stays `None` instead of being filled with "N/A" placeholders
+
+/// state of a pool as reported by one source
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct PoolSnapshot {
+	pub pool_id: u64,
+	pub denoms: Vec<String>,
+	/// swap fee or spread factor
+	pub fee: Option<Decimal>,
+	pub model: PoolModel,
+	pub liquidity_usd: Option<Decimal>,
+	/// units of `denoms[1]` per unit of `denoms[0]`
+	pub price: Option<Decimal>,
+}
+
+/// what is known of the pool internals, enough to simulate swaps for the chain kinds
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PoolModel {
+	Balancer {
+		assets: Vec<PoolAsset>,
+	},
+	/// Osmosis stableswap pool, priced by a solidly style CFMM rather than the
+	/// Curve invariant of `amm::StableswapPool`
+	Stableswap {
+		assets: Vec<StableAsset>,
+	},
+	Concentrated {
+		current_sqrt_price: Decimal,
+		current_tick: i64,
+		liquidity: Decimal,
+		tick_spacing: u64,
+		/// initialized ticks, empty until read from the chain
+		ticks: Vec<Tick>,
+	},
+	CosmWasm {
+		contract_address: String,
+		code_id: u64,
+	},
+	/// listed by an aggregator that does not expose the reserves
+	Unknown,
+}
+
+impl PoolSnapshot {
+	/// add the simulator of this pool to `pools`
+	///
+	/// CosmWasm and aggregator-only pools cannot be simulated and are rejected,
+	/// as are stableswap pools, whose CFMM is not modelled
+	pub fn insert_into(&self, pools: &mut PoolSet) -> Result<(), FeanorError> {
+		let fee = self.fee.ok_or(FeanorError::InvalidPool)?;
+		match &self.model {
+			PoolModel::Balancer { assets } => match assets.as_slice() {
+				[a, b] if a.weight == b.weight => {
+					pools.insert(self.pool_id, ConstantProduct::new([a.clone(), b.clone()], fee)?)
+				}
+				_ => pools.insert(self.pool_id, WeightedPool::new(assets.clone(), fee)?),
+			},
+			PoolModel::Concentrated { current_sqrt_price, current_tick, liquidity, ticks, .. } => {
+				let [token0, token1] = self.denoms.as_slice() else {
+					return Err(FeanorError::InvalidPool);
+				};
+				pools.insert(
+					self.pool_id,
+					ConcentratedPool::new(
+						token0.clone(),
+						token1.clone(),
+						*current_sqrt_price,
+						*current_tick,
+						*liquidity,
+						ticks.clone(),
+						fee,
+					)?,
+				)
+			}
+			PoolModel::Stableswap { .. } | PoolModel::CosmWasm { .. } | PoolModel::Unknown => {
+				return Err(FeanorError::InvalidPool)
+			}
+		}
+		Ok(())
+	}
+}
+
+/// why a source response could not be turned into a `PoolSnapshot`
+#[derive(Error, Clone, Debug, Eq, PartialEq)]
+pub enum ParseError {
+	#[error("malformed JSON: {0}")]
+	Json(String),
+	#[error("missing field '{0}'")]
+	MissingField(&'static str),
+	#[error("invalid number in '{field}': '{value}'")]
+	InvalidNumber { field: &'static str, value: String },
+	#[error("invalid denom '{0}'")]
+	InvalidDenom(String),
+	#[error("unsupported pool type '{0}'")]
+	UnsupportedPoolType(String),
+	#[error("no fee field in the pool")]
+	MissingFee,
+	#[error("invalid pair address '{0}'")]
+	InvalidPairAddress(String),
+	#[error("'{field}' lists {found} entries for {expected} assets")]
+	LengthMismatch { field: &'static str, expected: usize, found: usize },
+}
+
+/// why a source could not be queried
+#[derive(Error, Clone, Debug, Eq, PartialEq)]
+pub enum FetchError {
+	#[error("{url} answered HTTP {status}")]
+	Status { url: String, status: u16 },
+	#[error("transport error: {0}")]
+	Transport(String),
+	#[error(transparent)]
+	Parse(#[from] ParseError),
+	#[error("cache error: {0}")]
+	Cache(String),
+	#[error("{url} still had pairs after {pages} pages")]
+	TooManyPages { url: String, pages: usize },
+}
+
+impl From<serde_json::Error> for ParseError {
+	fn from(e: serde_json::Error) -> Self {
+		ParseError::Json(e.to_string())
+	}
+}
+
+/// decimal string of the chain, digits past the 18th being truncated
+///
+/// CL sqrt prices are 36 decimal `BigDec`s
+pub(crate) fn parse_decimal(field: &'static str, value: &str) -> Result<Decimal, ParseError> {
+	let truncated = match value.split_once('.') {
+		Some((whole, fraction)) if fraction.is_ascii() && fraction.len() > Decimal::DECIMAL_PLACES as usize => {
+			format!("{}.{}", whole, &fraction[..Decimal::DECIMAL_PLACES as usize])
+		}
+		_ => value.to_string(),
+	};
+	truncated.parse().map_err(|_| ParseError::InvalidNumber {
+		field,
+		value: value.to_string(),
+	})
+}
+
+/// integer sent as a JSON string, as the chain does for 64 bits and more
+pub(crate) fn parse_int<T: std::str::FromStr>(field: &'static str, value: &str) -> Result<T, ParseError> {
+	value.parse().map_err(|_| ParseError::InvalidNumber {
+		field,
+		value: value.to_string(),
+	})
+}
+
+/// JSON number as a decimal, negative and non finite values being invalid
+pub(crate) fn parse_float(field: &'static str, value: f64) -> Result<Decimal, ParseError> {
+	if !value.is_finite() || value < 0.0 {
+		return Err(ParseError::InvalidNumber {
+			field,
+			value: value.to_string(),
+		});
+	}
+	// `f64` display never uses an exponent
+	parse_decimal(field, &value.to_string())
+}
+
+pub(crate) fn check_denom(denom: &str) -> Result<String, ParseError> {
+	denom
+		.parse::<Denom>()
+		.map(|_| denom.to_string())
+		.map_err(|_| ParseError::InvalidDenom(denom.to_string()))
+}
@@ -0,0 +1,566 @@
+use std::{
+	env, fs,
+	path::PathBuf,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+use crate::feeds::{
+	check_denom, http::HttpClient, parse_float, parse_int, FetchError, ParseError, PoolModel, PoolSnapshot,
+};
+
+// Numia `/pairs/v2/summary` ingestion
+//
+// pages through every pair `limit` at a time like `getOrFetchAllNumiaPairs`,
+// with the bearer token taken from the environment instead of the source and
+// the full list kept in an on-disk cache until its TTL runs out
+
+pub const NUMIA_PAIRS_URL: &str = "https://osmosis.numia.xyz/pairs/v2/summary";
+/// environment variables read by `NumiaConfig::from_env`
+pub const NUMIA_TOKEN_ENV: &str = "NUMIA_API_TOKEN";
+pub const NUMIA_URL_ENV: &str = "NUMIA_PAIRS_URL";
+pub const NUMIA_CACHE_ENV: &str = "NUMIA_CACHE_PATH";
+
+/// page size requested, Numia serving up to this many pairs a page
+pub const PAGE_LIMIT: usize = 1000;
+/// pages read before giving up on a list that never ends
+pub const MAX_PAGES: usize = 100;
+const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(300);
+
+#[derive(Clone, Debug)]
+pub struct NumiaConfig {
+	pub pairs_url: String,
+	/// bearer token, requests are sent unauthenticated without one
+	pub token: Option<String>,
+	/// `limit` of each request, at most the server maximum since a shorter
+	/// page ends the list
+	pub page_limit: usize,
+	pub max_pages: usize,
+	/// no caching without a path
+	pub cache_path: Option<PathBuf>,
+	pub cache_ttl: Duration,
+}
+
+impl Default for NumiaConfig {
+	fn default() -> Self {
+		NumiaConfig {
+			pairs_url: NUMIA_PAIRS_URL.to_string(),
+			token: None,
+			page_limit: PAGE_LIMIT,
+			max_pages: MAX_PAGES,
+			cache_path: None,
+			cache_ttl: DEFAULT_CACHE_TTL,
+		}
+	}
+}
+
+impl NumiaConfig {
+	/// defaults overridden by `NUMIA_API_TOKEN`, `NUMIA_PAIRS_URL` and `NUMIA_CACHE_PATH`
+	pub fn from_env() -> Self {
+		let var = |name| env::var(name).ok().filter(|value: &String| !value.is_empty());
+		let default = NumiaConfig::default();
+		NumiaConfig {
+			pairs_url: var(NUMIA_URL_ENV).unwrap_or(default.pairs_url),
+			token: var(NUMIA_TOKEN_ENV),
+			cache_path: var(NUMIA_CACHE_ENV).map(PathBuf::from),
+			..default
+		}
+	}
+}
+
+/// one page of `/pairs/v2/summary`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Page {
+	#[serde(default)]
+	pub data: Vec<NumiaPair>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct NumiaPair {
+	pub pool_id: String,
+	/// pool or contract address
+	pub pool_address: String,
+	pub base_symbol: String,
+	pub base_address: String,
+	pub quote_symbol: String,
+	pub quote_address: String,
+	/// sent either as a number or a string
+	#[serde(default, deserialize_with = "number_or_string")]
+	pub liquidity_usd: Option<f64>,
+	/// price of the base token in quote tokens
+	#[serde(default, deserialize_with = "number_or_string")]
+	pub price: Option<f64>,
+}
+
+impl NumiaPair {
+	pub fn to_snapshot(&self) -> Result<PoolSnapshot, ParseError> {
+		Ok(PoolSnapshot {
+			pool_id: parse_int("pool_id", &self.pool_id)?,
+			denoms: vec![check_denom(&self.base_address)?, check_denom(&self.quote_address)?],
+			fee: None,
+			model: PoolModel::Unknown,
+			liquidity_usd: self.liquidity_usd.map(|usd| parse_float("liquidity_usd", usd)).transpose()?,
+			price: self.price.map(|price| parse_float("price", price)).transpose()?,
+		})
+	}
+}
+
+/// the cached pair list and when it was fetched
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+	/// unix seconds
+	fetched_at: u64,
+	pairs: Vec<NumiaPair>,
+}
+
+pub struct Numia<C> {
+	client: C,
+	config: NumiaConfig,
+}
+
+impl<C: HttpClient> Numia<C> {
+	pub fn new(client: C, config: NumiaConfig) -> Self {
+		Numia { client, config }
+	}
+
+	pub fn page_url(&self, offset: usize) -> String {
+		format!("{}?limit={}&offset={}", self.config.pairs_url, self.config.page_limit, offset)
+	}
+
+	pub fn page(&self, offset: usize) -> Result<Page, FetchError> {
+		let authorization = self.config.token.as_ref().map(|token| format!("Bearer {}", token));
+		let headers: Vec<(&str, &str)> = authorization
+			.iter()
+			.map(|value| ("Authorization", value.as_str()))
+			.collect();
+		let body = self.client.get(&self.page_url(offset), &headers)?;
+		serde_json::from_str(&body).map_err(|e| ParseError::from(e).into())
+	}
+
+	/// every pair, following the offset until an empty or short page
+	///
+	/// a page identical to the previous one means the server ignores the
+	/// offset and also ends the list; more than `max_pages` pages is an error
+	pub fn fetch_all(&self) -> Result<Vec<NumiaPair>, FetchError> {
+		let mut pairs = Vec::new();
+		let mut previous: Option<Vec<NumiaPair>> = None;
+		for _ in 0..self.config.max_pages {
+			let page = self.page(pairs.len())?;
+			if page.data.is_empty() || previous.as_ref() == Some(&page.data) {
+				return Ok(pairs);
+			}
+			let short = page.data.len() < self.config.page_limit;
+			pairs.extend_from_slice(&page.data);
+			if short {
+				return Ok(pairs);
+			}
+			previous = Some(page.data);
+		}
+		Err(FetchError::TooManyPages {
+			url: self.config.pairs_url.clone(),
+			pages: self.config.max_pages,
+		})
+	}
+
+	/// every pair, from the cache while it is fresh
+	///
+	/// a cache that cannot be written is logged, the pairs being fetched anyway
+	pub fn pairs(&self) -> Result<Vec<NumiaPair>, FetchError> {
+		if let Some(pairs) = self.read_cache() {
+			return Ok(pairs);
+		}
+		let pairs = self.fetch_all()?;
+		if let Err(e) = self.write_cache(&pairs) {
+			log::warn!("numia pairs not cached: {}", e);
+		}
+		Ok(pairs)
+	}
+
+	/// address of the pool `pool_id`, as `fetchNumiaContractAddress` looks it up
+	pub fn pool_address(&self, pool_id: u64) -> Result<Option<String>, FetchError> {
+		let pool_id = pool_id.to_string();
+		Ok(self
+			.pairs()?
+			.into_iter()
+			.find(|pair| pair.pool_id == pool_id)
+			.map(|pair| pair.pool_address))
+	}
+
+	/// cached pairs if the cache exists, parses and is younger than the TTL
+	fn read_cache(&self) -> Option<Vec<NumiaPair>> {
+		let path = self.config.cache_path.as_ref()?;
+		let cache: CacheFile = serde_json::from_slice(&fs::read(path).ok()?).ok()?;
+		let age = unix_now().checked_sub(cache.fetched_at)?;
+		(age < self.config.cache_ttl.as_secs()).then_some(cache.pairs)
+	}
+
+	fn write_cache(&self, pairs: &[NumiaPair]) -> Result<(), FetchError> {
+		let Some(path) = &self.config.cache_path else {
+			return Ok(());
+		};
+		let cache = CacheFile {
+			fetched_at: unix_now(),
+			pairs: pairs.to_vec(),
+		};
+		let json = serde_json::to_vec(&cache).map_err(|e| FetchError::Cache(e.to_string()))?;
+		// written aside then renamed so a reader never sees half a file
+		let partial = path.with_extension("partial");
+		fs::write(&partial, json)
+			.and_then(|_| fs::rename(&partial, path))
+			.map_err(|e| FetchError::Cache(format!("{}: {}", path.display(), e)))
+	}
+}
+
+fn unix_now() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|elapsed| elapsed.as_secs())
+		.unwrap_or(0)
+}
+
+/// `12.5`, `"12.5"` or `null`
+fn number_or_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+	#[derive(Deserialize)]
+	#[serde(untagged)]
+	enum Raw {
+		Number(f64),
+		Text(String),
+	}
+
+	match Option::<Raw>::deserialize(deserializer)? {
+		Some(Raw::Number(value)) => Ok(Some(value)),
+		Some(Raw::Text(text)) if text.is_empty() => Ok(None),
+		Some(Raw::Text(text)) => text
+			.parse()
+			.map(Some)
+			.map_err(|_| de::Error::custom(format!("invalid number '{}'", text))),
+		None => Ok(None),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		collections::HashMap,
+		io::{self, BufRead, BufReader, Write},
+		net::{SocketAddr, TcpListener, TcpStream},
+		sync::{
+			atomic::{AtomicBool, Ordering},
+			Arc, Mutex,
+		},
+		thread,
+	};
+
+	use serde_json::json;
+
+	use super::*;
+	use crate::feeds::http::UreqClient;
+
+	const PATH: &str = "/pairs/v2/summary";
+
+	fn pair(pool_id: u64) -> String {
+		json!({
+			"pool_id": pool_id.to_string(),
+			"pool_address": format!("osmo1pool{}", pool_id),
+			"base_symbol": "OSMO",
+			"base_address": "uosmo",
+			"quote_symbol": "ATOM",
+			"quote_address": "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2",
+			"liquidity_usd": "1250.5",
+			"price": 0.171
+		})
+		.to_string()
+	}
+
+	fn page(pool_ids: std::ops::Range<u64>) -> String {
+		let pairs: Vec<String> = pool_ids.map(pair).collect();
+		format!(r#"{{"data":[{}]}}"#, pairs.join(","))
+	}
+
+	/// target of a request and its headers, names in lowercase
+	type Request = (String, Vec<(String, String)>);
+
+	/// HTTP server on a local port, answering each GET with the body `respond`
+	/// gives for its target or 404, and remembering every request
+	struct MockServer {
+		addr: SocketAddr,
+		requests: Arc<Mutex<Vec<Request>>>,
+		stop: Arc<AtomicBool>,
+	}
+
+	impl MockServer {
+		fn start(respond: impl Fn(&str) -> Option<String> + Send + 'static) -> Self {
+			let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+			let addr = listener.local_addr().unwrap();
+			let requests = Arc::new(Mutex::new(Vec::new()));
+			let stop = Arc::new(AtomicBool::new(false));
+			let (recorded, stopped) = (requests.clone(), stop.clone());
+			thread::spawn(move || {
+				for stream in listener.incoming() {
+					if stopped.load(Ordering::SeqCst) {
+						break;
+					}
+					if let Ok(stream) = stream {
+						let _ = serve(stream, &respond, &recorded);
+					}
+				}
+			});
+			MockServer { addr, requests, stop }
+		}
+
+		/// `pages` by offset for requests of `page_limit` pairs
+		fn with_pages(page_limit: usize, pages: &[(usize, String)]) -> Self {
+			let pages: HashMap<String, String> = pages
+				.iter()
+				.map(|(offset, body)| (format!("{}?limit={}&offset={}", PATH, page_limit, offset), body.clone()))
+				.collect();
+			MockServer::start(move |target| pages.get(target).cloned())
+		}
+
+		fn url(&self) -> String {
+			format!("http://{}{}", self.addr, PATH)
+		}
+
+		fn requests(&self) -> Vec<Request> {
+			self.requests.lock().unwrap().clone()
+		}
+
+		fn targets(&self) -> Vec<String> {
+			self.requests().into_iter().map(|(target, _)| target).collect()
+		}
+	}
+
+	impl Drop for MockServer {
+		fn drop(&mut self) {
+			self.stop.store(true, Ordering::SeqCst);
+			// wakes the accept loop up to see the flag
+			let _ = TcpStream::connect(self.addr);
+		}
+	}
+
+	/// answer one request, recorded before the response is written
+	fn serve(
+		mut stream: TcpStream,
+		respond: &impl Fn(&str) -> Option<String>,
+		requests: &Mutex<Vec<Request>>,
+		) -> io::Result<()> {
+		let mut reader = BufReader::new(stream.try_clone()?);
+		let mut line = String::new();
+		// `GET <target> HTTP/1.1`
+		reader.read_line(&mut line)?;
+		let target = line.split_whitespace().nth(1).unwrap_or_default().to_string();
+		let mut headers = Vec::new();
+		loop {
+			line.clear();
+			reader.read_line(&mut line)?;
+			let Some((name, value)) = line.trim_end().split_once(':') else {
+				break;
+			};
+			headers.push((name.to_ascii_lowercase(), value.trim().to_string()));
+		}
+		let body = respond(&target);
+		requests.lock().unwrap().push((target, headers));
+
+		let (status, body) = match body {
+			Some(body) => ("200 OK", body),
+			None => ("404 Not Found", String::new()),
+		};
+		write!(
+			stream,
+			"HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+			status,
+			body.len(),
+			body
+		)
+	}
+
+	fn config(server: &MockServer, page_limit: usize) -> NumiaConfig {
+		NumiaConfig {
+			pairs_url: server.url(),
+			page_limit,
+			..NumiaConfig::default()
+		}
+	}
+
+	fn numia(config: NumiaConfig) -> Numia<UreqClient> {
+		Numia::new(UreqClient::new(Duration::from_secs(5)), config)
+	}
+
+	fn target(page_limit: usize, offset: usize) -> String {
+		format!("{}?limit={}&offset={}", PATH, page_limit, offset)
+	}
+
+	/// a cache path of its own for each test, removed on drop
+	struct TempCache(PathBuf);
+
+	impl TempCache {
+		fn new(name: &str) -> Self {
+			TempCache(env::temp_dir().join(format!("feanor-numia-{}-{}.json", name, std::process::id())))
+		}
+	}
+
+	impl Drop for TempCache {
+		fn drop(&mut self) {
+			let _ = fs::remove_file(&self.0);
+		}
+	}
+
+	#[test]
+	fn follows_pages_until_a_short_one() {
+		let server = MockServer::with_pages(3, &[(0, page(0..3)), (3, page(3..6)), (6, page(6..7))]);
+		let pairs = numia(config(&server, 3)).fetch_all().unwrap();
+
+		let ids: Vec<&str> = pairs.iter().map(|pair| pair.pool_id.as_str()).collect();
+		assert_eq!(ids, vec!["0", "1", "2", "3", "4", "5", "6"]);
+		assert_eq!(server.targets(), vec![target(3, 0), target(3, 3), target(3, 6)]);
+		assert_eq!(pairs[0].liquidity_usd, Some(1250.5));
+		assert_eq!(pairs[0].price, Some(0.171));
+		pairs[0].to_snapshot().unwrap();
+	}
+
+	#[test]
+	fn stops_at_an_empty_page() {
+		let server = MockServer::with_pages(3, &[(0, page(0..3)), (3, page(3..6)), (6, page(0..0))]);
+		assert_eq!(numia(config(&server, 3)).fetch_all().unwrap().len(), 6);
+		assert_eq!(server.targets().len(), 3);
+	}
+
+	#[test]
+	fn stops_when_the_server_ignores_the_offset() {
+		let server = MockServer::start(|_| Some(page(0..3)));
+		let pairs = numia(config(&server, 3)).fetch_all().unwrap();
+		assert_eq!(pairs.len(), 3);
+		assert_eq!(server.targets(), vec![target(3, 0), target(3, 3)]);
+	}
+
+	#[test]
+	fn gives_up_on_a_list_that_never_ends() {
+		// a full page of new pairs at every offset
+		let server = MockServer::start(|target| {
+			let offset: u64 = target.rsplit_once("offset=")?.1.parse().ok()?;
+			Some(page(offset..offset + 2))
+		});
+		let config = NumiaConfig {
+			max_pages: 5,
+			..config(&server, 2)
+		};
+		assert_eq!(
+			numia(config).fetch_all(),
+			Err(FetchError::TooManyPages {
+				url: server.url(),
+				pages: 5,
+			})
+		);
+		assert_eq!(server.targets().len(), 5);
+	}
+
+	#[test]
+	fn sends_the_bearer_token() {
+		let server = MockServer::with_pages(1000, &[(0, page(0..0))]);
+		numia(config(&server, 1000)).fetch_all().unwrap();
+		let config = NumiaConfig {
+			token: Some("secret".to_string()),
+			..config(&server, 1000)
+		};
+		numia(config).fetch_all().unwrap();
+
+		let authorization: Vec<Vec<String>> = server
+			.requests()
+			.into_iter()
+			.map(|(_, headers)| {
+				headers
+					.into_iter()
+					.filter(|(name, _)| name == "authorization")
+					.map(|(_, value)| value)
+					.collect()
+			})
+			.collect();
+		assert_eq!(authorization, vec![vec![], vec!["Bearer secret".to_string()]]);
+	}
+
+	#[test]
+	fn reports_http_errors() {
+		let server = MockServer::start(|_| None);
+		let url = format!("{}?limit=1000&offset=0", server.url());
+		assert_eq!(
+			numia(config(&server, 1000)).fetch_all(),
+			Err(FetchError::Status { url, status: 404 })
+		);
+	}
+
+	#[test]
+	fn fresh_cache_is_served_without_a_request() {
+		let cache = TempCache::new("fresh");
+		let cached = CacheFile {
+			fetched_at: unix_now(),
+			pairs: vec![serde_json::from_str(&pair(42)).unwrap()],
+		};
+		fs::write(&cache.0, serde_json::to_vec(&cached).unwrap()).unwrap();
+
+		let server = MockServer::start(|_| None);
+		let config = NumiaConfig {
+			cache_path: Some(cache.0.clone()),
+			..config(&server, 1000)
+		};
+		let numia = numia(config);
+		assert_eq!(numia.pool_address(42).unwrap(), Some("osmo1pool42".to_string()));
+		assert!(server.targets().is_empty());
+	}
+
+	#[test]
+	fn expired_cache_is_fetched_again_and_rewritten() {
+		let cache = TempCache::new("expired");
+		let stale = CacheFile {
+			fetched_at: unix_now() - 600,
+			pairs: vec![serde_json::from_str(&pair(42)).unwrap()],
+		};
+		fs::write(&cache.0, serde_json::to_vec(&stale).unwrap()).unwrap();
+
+		let server = MockServer::with_pages(1000, &[(0, page(1..3))]);
+		let config = NumiaConfig {
+			cache_path: Some(cache.0.clone()),
+			cache_ttl: Duration::from_secs(300),
+			..config(&server, 1000)
+		};
+		let numia = numia(config);
+		assert_eq!(numia.pool_address(42).unwrap(), None);
+		assert_eq!(numia.pool_address(2).unwrap(), Some("osmo1pool2".to_string()));
+		// the second lookup is served by the rewritten cache
+		assert_eq!(server.targets().len(), 1);
+
+		let rewritten: CacheFile = serde_json::from_slice(&fs::read(&cache.0).unwrap()).unwrap();
+		assert!(rewritten.fetched_at + 60 > unix_now());
+		assert_eq!(rewritten.pairs.len(), 2);
+		assert!(!cache.0.with_extension("partial").exists());
+	}
+
+	#[test]
+	fn unreadable_cache_is_ignored() {
+		let cache = TempCache::new("corrupt");
+		fs::write(&cache.0, b"{ not json").unwrap();
+		let server = MockServer::with_pages(1000, &[(0, page(7..8))]);
+		let config = NumiaConfig {
+			cache_path: Some(cache.0.clone()),
+			..config(&server, 1000)
+		};
+		assert_eq!(numia(config).pairs().unwrap().len(), 1);
+		assert_eq!(server.targets().len(), 1);
+	}
+
+	#[test]
+	fn unwritable_cache_does_not_fail_the_fetch() {
+		let missing = env::temp_dir().join(format!("feanor-numia-missing-{}", std::process::id()));
+		let server = MockServer::with_pages(1000, &[(0, page(0..2))]);
+		let config = NumiaConfig {
+			cache_path: Some(missing.join("pairs.json")),
+			..config(&server, 1000)
+		};
+		let numia = numia(config);
+		assert_eq!(numia.pairs().unwrap().len(), 2);
+		assert!(!missing.exists());
+		// nothing cached, the next call fetches again
+		assert_eq!(numia.pairs().unwrap().len(), 2);
+		assert_eq!(server.targets().len(), 2);
+	}
+}
@@ -0,0 +1,379 @@
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	denom::Denom,
+	error::FeanorError,
+	math::{Decimal, Rounding},
+	route::{Route, RouteEvaluation},
+};
+
+// White Whale `flash_loan` message builder
+//
+// turns an evaluated route into the `{flash_loan: {assets, msgs}}` JSON that
+// `wrapNoOsmoJson` assembles by hand, one wasm execute per step. native legs
+// swap on the pool contract with the funds attached; CW20 legs `send` the
+// tokens to the pool with a swap hook, pair contracts only accepting native
+// offers on a direct `swap`
+
+/// White Whale flash loan contract on Osmosis
+pub const WHITE_WHALE_FLASH_LOAN_CONTRACT: &str =
+	"osmo1javcdeqdnlujsrl4kduwfcs2cw5hd4jz9vh2wdpqyz6kp2tn8e9qt0rz8g";
+
+/// decimals of `belief_price`, as produced by BigNumber `toFixed(18)`
+const BELIEF_PRICE_DECIMALS: u32 = 18;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetInfo {
+	NativeToken { denom: String },
+	Token { contract_addr: String },
+}
+
+impl AssetInfo {
+	/// CW20 tokens are denominated by their contract address
+	pub fn from_denom(denom: &Denom) -> Self {
+		match denom {
+			Denom::Cw20 { addr } => AssetInfo::Token { contract_addr: addr.to_string() },
+			native => AssetInfo::NativeToken { denom: native.to_string() },
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct Asset {
+	pub info: AssetInfo,
+	pub amount: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct Coin {
+	pub denom: String,
+	pub amount: String,
+}
+
+/// message of a pair contract swap, sent directly for native offers
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PairExecuteMsg {
+	Swap {
+		offer_asset: Asset,
+		ask_asset_info: AssetInfo,
+		belief_price: String,
+		max_spread: Decimal,
+	},
+}
+
+/// hook embedded in a CW20 `send` to a pair contract
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Cw20HookMsg {
+	Swap {
+		belief_price: String,
+		max_spread: Decimal,
+	},
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Cw20ExecuteMsg {
+	Send {
+		contract: String,
+		amount: String,
+		/// base64 encoded `Cw20HookMsg`
+		msg: String,
+	},
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct WasmExecute {
+	pub contract_addr: String,
+	/// base64 encoded JSON message
+	pub msg: String,
+	pub funds: Vec<Coin>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WasmMsg {
+	Execute(WasmExecute),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CosmosMsg {
+	Wasm(WasmMsg),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct FlashLoan {
+	pub assets: Vec<Asset>,
+	pub msgs: Vec<CosmosMsg>,
+}
+
+/// the message executed on the White Whale flash loan contract
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct FlashLoanMsg {
+	pub flash_loan: FlashLoan,
+}
+
+impl FlashLoanMsg {
+	pub fn to_json(&self) -> String {
+		serde_json::to_string(self).expect("flash loan serialization cannot fail")
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct FlashLoanOptions {
+	/// `max_spread` of every swap, also shaved off the amount offered to the next step
+	pub slippage: Decimal,
+}
+
+impl Default for FlashLoanOptions {
+	fn default() -> Self {
+		FlashLoanOptions {
+			slippage: Decimal::bps(50),
+		}
+	}
+}
+
+/// build the flash loan for `route` as simulated in `evaluation`
+///
+/// `pool_contracts` maps the pool ids of the route to their pair contract address
+pub fn build_flash_loan(
+	route: &Route,
+	evaluation: &RouteEvaluation,
+	pool_contracts: &HashMap<u64, String>,
+	options: &FlashLoanOptions,
+	) -> Result<FlashLoanMsg, FeanorError> {
+	route.validate()?;
+	if route.steps.len() != evaluation.steps.len() {
+		return Err(FeanorError::InvalidRoute);
+	}
+	let loan = match AssetInfo::from_denom(&route.from.denom.parse()?) {
+		info @ AssetInfo::NativeToken { .. } => info,
+		// White Whale only lends native coins
+		AssetInfo::Token { .. } => return Err(FeanorError::InvalidRoute),
+	};
+
+	let shave = Decimal::ONE.checked_sub(options.slippage)?;
+	let mut msgs = Vec::with_capacity(route.steps.len());
+	let mut offer_amount = evaluation.amount_in;
+
+	for (step, simulated) in route.steps.iter().zip(&evaluation.steps) {
+		if step.pool_id() != Some(simulated.pool_id) || step.from_token.denom != simulated.denom_in {
+			return Err(FeanorError::InvalidRoute);
+		}
+		let pair = pool_contracts
+			.get(&simulated.pool_id)
+			.ok_or(FeanorError::PoolNotFound)?;
+
+		// price of the ask asset in the offer asset, as simulated
+		let belief_price = Decimal::from_ratio(simulated.amount_in, simulated.amount_out, Rounding::Up)?
+			.to_fixed(BELIEF_PRICE_DECIMALS, Rounding::Up)?;
+		let offer = AssetInfo::from_denom(&simulated.denom_in.parse()?);
+		let ask = AssetInfo::from_denom(&simulated.denom_out.parse()?);
+
+		msgs.push(swap_msg(pair, offer, ask, offer_amount, belief_price, options));
+
+		// only what is certain to arrive is offered to the next step
+		offer_amount = shave.mul_int(simulated.amount_out, Rounding::Down)?;
+		if offer_amount == 0 {
+			return Err(FeanorError::UnprofitableRoute);
+		}
+	}
+
+	Ok(FlashLoanMsg {
+		flash_loan: FlashLoan {
+			assets: vec![Asset {
+				info: loan,
+				amount: evaluation.amount_in.to_string(),
+			}],
+			msgs,
+		},
+	})
+}
+
+/// wasm message swapping `amount` of `offer` for `ask` on `pair`
+fn swap_msg(
+	pair: &str,
+	offer: AssetInfo,
+	ask: AssetInfo,
+	amount: u128,
+	belief_price: String,
+	options: &FlashLoanOptions,
+	) -> CosmosMsg {
+	let amount = amount.to_string();
+	match offer {
+		AssetInfo::NativeToken { ref denom } => {
+			let funds = vec![Coin { denom: denom.clone(), amount: amount.clone() }];
+			let swap = PairExecuteMsg::Swap {
+				offer_asset: Asset { info: offer, amount },
+				ask_asset_info: ask,
+				belief_price,
+				max_spread: options.slippage,
+			};
+			execute(pair, &swap, funds)
+		}
+		AssetInfo::Token { contract_addr } => {
+			let hook = Cw20HookMsg::Swap {
+				belief_price,
+				max_spread: options.slippage,
+			};
+			let send = Cw20ExecuteMsg::Send {
+				contract: pair.to_string(),
+				amount,
+				msg: to_base64(&hook),
+			};
+			execute(&contract_addr, &send, Vec::new())
+		}
+	}
+}
+
+fn execute<T: Serialize>(contract_addr: &str, msg: &T, funds: Vec<Coin>) -> CosmosMsg {
+	CosmosMsg::Wasm(WasmMsg::Execute(WasmExecute {
+		contract_addr: contract_addr.to_string(),
+		msg: to_base64(msg),
+		funds,
+	}))
+}
+
+fn to_base64<T: Serialize>(msg: &T) -> String {
+	STANDARD.encode(serde_json::to_vec(msg).expect("message serialization cannot fail"))
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::{json, Value};
+
+	use super::*;
+	use crate::{
+		denom::Addr,
+		route::{RouteStep, RouteToken, StepEvaluation},
+	};
+
+	fn contract(byte: u8) -> String {
+		Addr::from_bytes("osmo", &[byte; 32]).unwrap().to_string()
+	}
+
+	fn simulated(pool_id: u64, denom_in: &str, denom_out: &str, amount_in: u128, amount_out: u128) -> StepEvaluation {
+		StepEvaluation {
+			pool_id,
+			denom_in: denom_in.into(),
+			denom_out: denom_out.into(),
+			amount_in,
+			amount_out,
+			fee_paid: 0,
+			price_impact: Decimal::ZERO,
+		}
+	}
+
+	// uosmo -> CW20 token on pool 1, then back on pool 2
+	fn setup() -> (Route, RouteEvaluation, HashMap<u64, String>) {
+		let osmo = RouteToken::new("osmo", "uosmo", 6);
+		let token = RouteToken::new("tkn", &contract(9), 6);
+		let route = Route {
+			from: osmo.clone(),
+			to: osmo.clone(),
+			steps: vec![
+				RouteStep::new(1, Decimal::ZERO, osmo.clone(), token.clone(), "osmosis"),
+				RouteStep::new(2, Decimal::ZERO, token.clone(), osmo.clone(), "osmosis"),
+			],
+		};
+		let evaluation = RouteEvaluation {
+			amount_in: 1_000_000,
+			amount_out: 1_010_000,
+			steps: vec![
+				simulated(1, "uosmo", &token.denom, 1_000_000, 2_000_000),
+				simulated(2, &token.denom, "uosmo", 2_000_000, 1_010_000),
+			],
+			net_profit: 10_000,
+		};
+		let contracts = HashMap::from([(1, contract(1)), (2, contract(2))]);
+		(route, evaluation, contracts)
+	}
+
+	fn decode(msg: &Value) -> Value {
+		serde_json::from_slice(&STANDARD.decode(msg.as_str().unwrap()).unwrap()).unwrap()
+	}
+
+	#[test]
+	fn builds_the_white_whale_message() {
+		let (route, evaluation, contracts) = setup();
+		let msg = build_flash_loan(&route, &evaluation, &contracts, &FlashLoanOptions::default()).unwrap();
+		let json: Value = serde_json::from_str(&msg.to_json()).unwrap();
+
+		assert_eq!(
+			json["flash_loan"]["assets"],
+			json!([{"info": {"native_token": {"denom": "uosmo"}}, "amount": "1000000"}])
+		);
+		let msgs = json["flash_loan"]["msgs"].as_array().unwrap();
+		assert_eq!(msgs.len(), 2);
+
+		// the native leg swaps on the pair with the offer attached
+		let execute = &msgs[0]["wasm"]["execute"];
+		assert_eq!(execute["contract_addr"], json!(contract(1)));
+		assert_eq!(execute["funds"], json!([{"denom": "uosmo", "amount": "1000000"}]));
+		assert_eq!(
+			decode(&execute["msg"]),
+			json!({"swap": {
+				"offer_asset": {"info": {"native_token": {"denom": "uosmo"}}, "amount": "1000000"},
+				"ask_asset_info": {"token": {"contract_addr": contract(9)}},
+				"belief_price": "0.500000000000000000",
+				"max_spread": "0.005"
+			}})
+		);
+
+		// the CW20 leg sends what surely arrived to the pair with a swap hook
+		let execute = &msgs[1]["wasm"]["execute"];
+		assert_eq!(execute["contract_addr"], json!(contract(9)));
+		assert_eq!(execute["funds"], json!([]));
+		let send = decode(&execute["msg"]);
+		assert_eq!(send["send"]["contract"], json!(contract(2)));
+		assert_eq!(send["send"]["amount"], json!("1990000"));
+		assert_eq!(
+			decode(&send["send"]["msg"]),
+			json!({"swap": {"belief_price": "1.980198019801980199", "max_spread": "0.005"}})
+		);
+	}
+
+	#[test]
+	fn rejects_what_it_cannot_execute() {
+		let options = FlashLoanOptions::default();
+		let (route, evaluation, contracts) = setup();
+
+		let mut unknown_pair = contracts.clone();
+		unknown_pair.remove(&2);
+		assert_eq!(
+			build_flash_loan(&route, &evaluation, &unknown_pair, &options),
+			Err(FeanorError::PoolNotFound)
+		);
+
+		let mut short = evaluation.clone();
+		short.steps.pop();
+		assert_eq!(build_flash_loan(&route, &short, &contracts, &options), Err(FeanorError::InvalidRoute));
+
+		let mut other_pool = evaluation.clone();
+		other_pool.steps[0].pool_id = 3;
+		assert_eq!(build_flash_loan(&route, &other_pool, &contracts, &options), Err(FeanorError::InvalidRoute));
+
+		// White Whale lends native coins only
+		let token = route.steps[1].from_token.clone();
+		let cw20_loan = Route {
+			from: token.clone(),
+			to: token,
+			steps: vec![route.steps[1].clone(), route.steps[0].clone()],
+		};
+		assert_eq!(cw20_loan.validate(), Ok(()));
+		assert_eq!(build_flash_loan(&cw20_loan, &evaluation, &contracts, &options), Err(FeanorError::InvalidRoute));
+
+		// a slippage eating the whole output leaves nothing for the next step
+		let options = FlashLoanOptions { slippage: Decimal::ONE };
+		assert_eq!(
+			build_flash_loan(&route, &evaluation, &contracts, &options),
+			Err(FeanorError::UnprofitableRoute)
+		);
+	}
+}
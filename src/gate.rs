@@ -0,0 +1,84 @@
+use mpl_token_metadata::accounts::Metadata;
+use solana_program::{
+	account_info::{next_account_info, AccountInfo},
+	program_error::ProgramError,
+	program_pack::Pack,
+	pubkey::Pubkey,
+};
+use spl_token::state::Account as TokenAccount;
+
+use crate::{error::FeanorError, state::GateConfig};
+
+// Varda NFT ownership gate shared by every vault and execution instruction
+
+/// check that `holder` owns a Varda NFT, consuming the gate accounts from `accounts`
+///
+/// gate accounts:
+/// 0. `[]` gate config PDA
+/// 1. `[]` NFT token account of the holder
+/// 2. `[]` Metaplex metadata of the NFT, only when the gate verifies collections
+///
+/// returns the mint of the held NFT
+pub fn check_varda_holder<'a, 'b: 'a, I>(
+	program_id: &Pubkey,
+	holder: &AccountInfo<'b>,
+	accounts: &mut I,
+	) -> Result<Pubkey, ProgramError>
+where
+	I: Iterator<Item = &'a AccountInfo<'b>>,
+{
+	let gate_info = next_account_info(accounts)?;
+	let token_info = next_account_info(accounts)?;
+
+	let config = GateConfig::load(gate_info, program_id)?;
+
+	// the holder must own exactly one token of the NFT
+
+	if *token_info.owner != spl_token::id() {
+		return Err(FeanorError::NftGateFailed.into());
+	}
+	let token = TokenAccount::unpack(&token_info.data.borrow())
+		.map_err(|_| FeanorError::NftGateFailed)?;
+	if token.owner != *holder.key || token.amount != 1 {
+		return Err(FeanorError::NftGateFailed.into());
+	}
+
+	if !config.verify_collection {
+		if token.mint != config.collection_mint {
+			return Err(FeanorError::NftGateFailed.into());
+		}
+		return Ok(token.mint);
+	}
+
+	// otherwise the NFT metadata must carry the verified Varda collection
+
+	let metadata_info = next_account_info(accounts)?;
+	check_collection(metadata_info, &token.mint, &config.collection_mint)?;
+	Ok(token.mint)
+}
+
+/// address of the Metaplex metadata account of `mint`
+pub fn find_metadata_address(mint: &Pubkey) -> Pubkey {
+	Metadata::find_pda(mint).0
+}
+
+fn check_collection(
+	metadata_info: &AccountInfo,
+	mint: &Pubkey,
+	collection_mint: &Pubkey,
+	) -> Result<(), ProgramError> {
+	if *metadata_info.owner != mpl_token_metadata::ID
+		|| *metadata_info.key != find_metadata_address(mint)
+	{
+		return Err(FeanorError::NftCollectionNotVerified.into());
+	}
+	let metadata = Metadata::safe_deserialize(&metadata_info.data.borrow())
+		.map_err(|_| FeanorError::NftCollectionNotVerified)?;
+	if metadata.mint != *mint {
+		return Err(FeanorError::NftCollectionNotVerified.into());
+	}
+	match metadata.collection {
+		Some(collection) if collection.verified && collection.key == *collection_mint => Ok(()),
+		_ => Err(FeanorError::NftCollectionNotVerified.into()),
+	}
+}
@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+
+use crate::{
+	amm::PoolSet,
+	math::Decimal,
+	route::{Route, RouteStep, RouteToken},
+};
+
+// arbitrage cycle detection over the token / pool multigraph
+//
+// every pool contributes one edge per ordered pair of its tokens, weighted by
+// -ln(spot price * (1 - fee)); a cycle whose weights sum below zero returns
+// more than it takes. cycles are found with a hop bounded Bellman-Ford
+// relaxation that keeps the best few paths per token instead of only one
+
+/// provider name written in the steps of detected routes
+const POOL_PROVIDER: &str = "osmosis";
+
+/// directed edge: swapping `denom_in` for `denom_out` through `pool_id`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Edge {
+	pub pool_id: u64,
+	pub denom_in: String,
+	pub denom_out: String,
+	pub fee: Decimal,
+	/// -ln(spot price * (1 - fee))
+	pub weight: f64,
+}
+
+/// limits of a cycle search
+#[derive(Clone, Debug)]
+pub struct CycleSearch {
+	/// longest cycle, in swaps
+	pub max_hops: usize,
+	/// paths kept per token at every hop
+	pub beam_width: usize,
+	/// cycles returned
+	pub max_results: usize,
+}
+
+impl Default for CycleSearch {
+	fn default() -> Self {
+		CycleSearch {
+			max_hops: 4,
+			beam_width: 8,
+			max_results: 20,
+		}
+	}
+}
+
+/// a detected cycle and the route that executes it
+#[derive(Clone, Debug)]
+pub struct Candidate {
+	pub route: Route,
+	/// product of the fee adjusted spot prices along the cycle, above 1 when profitable
+	pub rate: f64,
+}
+
+pub struct TokenGraph {
+	edges: Vec<Edge>,
+	/// edge indices leaving each denom
+	adjacency: HashMap<String, Vec<usize>>,
+}
+
+// partial path of the relaxation
+
+#[derive(Clone)]
+struct Path {
+	edges: Vec<usize>,
+	weight: f64,
+}
+
+impl TokenGraph {
+	/// build the graph from the spot prices of every pool in `pools`
+	///
+	/// pairs whose price cannot be computed, e.g. a drained side, are left out
+	pub fn from_pools(pools: &PoolSet) -> Self {
+		let mut graph = TokenGraph {
+			edges: Vec::new(),
+			adjacency: HashMap::new(),
+		};
+		for (pool_id, pool) in pools.iter() {
+			let tokens = pool.tokens();
+			let fee_complement = 1.0 - to_f64(pool.fee());
+			for denom_in in &tokens {
+				for denom_out in &tokens {
+					if denom_in == denom_out {
+						continue;
+					}
+					let rate = match pool.spot_price(denom_in, denom_out) {
+						Ok(price) if !price.is_zero() => to_f64(price) * fee_complement,
+						_ => continue,
+					};
+					graph.add_edge(Edge {
+						pool_id,
+						denom_in: denom_in.to_string(),
+						denom_out: denom_out.to_string(),
+						fee: pool.fee(),
+						weight: -rate.ln(),
+					});
+				}
+			}
+		}
+		graph
+	}
+
+	pub fn add_edge(&mut self, edge: Edge) {
+		self.adjacency
+			.entry(edge.denom_in.clone())
+			.or_default()
+			.push(self.edges.len());
+		self.edges.push(edge);
+	}
+
+	pub fn edges(&self) -> &[Edge] {
+		&self.edges
+	}
+
+	/// profitable cycles starting and ending on `start`, most profitable first
+	///
+	/// `token` supplies the symbol, decimals and logo written in the routes
+	pub fn find_cycles(
+		&self,
+		start: &str,
+		search: &CycleSearch,
+		token: &dyn Fn(&str) -> RouteToken,
+		) -> Vec<Candidate> {
+		let mut cycles: Vec<Path> = Vec::new();
+		let mut frontier: HashMap<&str, Vec<Path>> = HashMap::new();
+		frontier.insert(start, vec![Path { edges: Vec::new(), weight: 0.0 }]);
+
+		for hop in 1..=search.max_hops {
+			let mut next: HashMap<&str, Vec<Path>> = HashMap::new();
+			for (denom, paths) in &frontier {
+				for &index in self.adjacency.get(*denom).into_iter().flatten() {
+					let edge = &self.edges[index];
+					for path in paths {
+						// going straight back through the same pool never pays
+						if path.edges.last().map(|&last| self.edges[last].pool_id) == Some(edge.pool_id) {
+							continue;
+						}
+						let weight = path.weight + edge.weight;
+						let mut edges = path.edges.clone();
+						edges.push(index);
+
+						if edge.denom_out == start {
+							if hop >= 2 && weight < 0.0 {
+								cycles.push(Path { edges, weight });
+							}
+							continue;
+						}
+						if self.visits(path, &edge.denom_out) {
+							continue;
+						}
+						next.entry(edge.denom_out.as_str())
+							.or_default()
+							.push(Path { edges, weight });
+					}
+				}
+			}
+
+			// relax: keep only the lightest paths reaching each token
+			for paths in next.values_mut() {
+				paths.sort_by(|a, b| a.weight.total_cmp(&b.weight));
+				paths.truncate(search.beam_width);
+			}
+			frontier = next;
+		}
+
+		cycles.sort_by(|a, b| a.weight.total_cmp(&b.weight));
+		cycles
+			.into_iter()
+			.take(search.max_results)
+			.map(|cycle| self.candidate(&cycle, start, token))
+			.collect()
+	}
+
+	fn visits(&self, path: &Path, denom: &str) -> bool {
+		path.edges.iter().any(|&index| self.edges[index].denom_out == denom)
+	}
+
+	fn candidate(&self, cycle: &Path, start: &str, token: &dyn Fn(&str) -> RouteToken) -> Candidate {
+		let steps = cycle
+			.edges
+			.iter()
+			.map(|&index| {
+				let edge = &self.edges[index];
+				RouteStep::new(
+					edge.pool_id,
+					edge.fee,
+					token(&edge.denom_in),
+					token(&edge.denom_out),
+					POOL_PROVIDER,
+				)
+			})
+			.collect();
+		Candidate {
+			route: Route {
+				from: token(start),
+				to: token(start),
+				steps,
+			},
+			rate: (-cycle.weight).exp(),
+		}
+	}
+}
+
+/// lossy conversion used for ranking only, never for amounts
+fn to_f64(value: Decimal) -> f64 {
+	value.atomics() as f64 / 1e18
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::amm::{ConstantProduct, PoolAsset};
+
+	fn pool(a: (&str, u128), b: (&str, u128), fee: Decimal) -> ConstantProduct {
+		let asset = |(denom, reserve): (&str, u128)| PoolAsset { denom: denom.into(), reserve, weight: 1 };
+		ConstantProduct::new([asset(a), asset(b)], fee).unwrap()
+	}
+
+	fn token(denom: &str) -> RouteToken {
+		RouteToken::new(denom.trim_start_matches('u'), denom, 6)
+	}
+
+	// 1 osmo buys 0.5 atom, 1 atom buys 4 usdc and 1 usdc buys 1.5 osmo: going
+	// around returns three times the input before fees, the other way a third
+	fn triangle() -> PoolSet {
+		let mut pools = PoolSet::new();
+		pools.insert(1, pool(("uosmo", 1_000_000), ("uatom", 500_000), Decimal::bps(20)));
+		pools.insert(2, pool(("uatom", 500_000), ("uusdc", 2_000_000), Decimal::ZERO));
+		pools.insert(3, pool(("uusdc", 2_000_000), ("uosmo", 3_000_000), Decimal::ZERO));
+		pools
+	}
+
+	fn pool_ids(candidate: &Candidate) -> Vec<u64> {
+		candidate.route.steps.iter().map(|step| step.pool_id().unwrap()).collect()
+	}
+
+	#[test]
+	fn weighs_each_direction_of_every_pool() {
+		let graph = TokenGraph::from_pools(&triangle());
+		assert_eq!(graph.edges().len(), 6);
+		let edge = graph
+			.edges()
+			.iter()
+			.find(|edge| edge.pool_id == 1 && edge.denom_in == "uosmo")
+			.unwrap();
+		assert_eq!(edge.denom_out, "uatom");
+		assert!((edge.weight + (0.5f64 * 0.998).ln()).abs() < 1e-12, "{}", edge.weight);
+	}
+
+	#[test]
+	fn finds_the_triangle_in_its_profitable_direction_only() {
+		let pools = triangle();
+		let graph = TokenGraph::from_pools(&pools);
+		let candidates = graph.find_cycles("uosmo", &CycleSearch::default(), &token);
+		assert_eq!(candidates.len(), 1);
+		assert_eq!(pool_ids(&candidates[0]), vec![1, 2, 3]);
+		assert!((candidates[0].rate - 3.0 * 0.998).abs() < 1e-9, "{}", candidates[0].rate);
+
+		let route = &candidates[0].route;
+		assert_eq!(route.validate(), Ok(()));
+		assert_eq!(route.from.denom, "uosmo");
+		assert_eq!(route.steps[0].swap_fee, "0.002");
+		assert!(route.evaluate(1_000, &pools).unwrap().is_profitable());
+
+		// the same cycle found from another of its tokens
+		let from_atom = graph.find_cycles("uatom", &CycleSearch::default(), &token);
+		assert_eq!(pool_ids(&from_atom[0]), vec![2, 3, 1]);
+	}
+
+	#[test]
+	fn respects_the_hop_limit() {
+		let graph = TokenGraph::from_pools(&triangle());
+		let search = CycleSearch { max_hops: 2, ..CycleSearch::default() };
+		assert!(graph.find_cycles("uosmo", &search, &token).is_empty());
+	}
+
+	#[test]
+	fn ignores_cycles_eaten_by_fees() {
+		// 1 osmo -> 0.5 atom -> 2 usdc -> 1 osmo, so any fee makes it a loss
+		let fee = Decimal::bps(30);
+		let mut pools = PoolSet::new();
+		pools.insert(1, pool(("uosmo", 1_000_000), ("uatom", 500_000), fee));
+		pools.insert(2, pool(("uatom", 500_000), ("uusdc", 1_000_000), fee));
+		pools.insert(3, pool(("uusdc", 1_000_000), ("uosmo", 1_000_000), fee));
+		let graph = TokenGraph::from_pools(&pools);
+		assert!(graph.find_cycles("uosmo", &CycleSearch::default(), &token).is_empty());
+	}
+
+	#[test]
+	fn ranks_cycles_and_caps_the_results() {
+		// a second osmo / atom pool pricing atom lower adds two hop cycles
+		let mut pools = triangle();
+		pools.insert(4, pool(("uosmo", 1_000_000), ("uatom", 400_000), Decimal::ZERO));
+		let graph = TokenGraph::from_pools(&pools);
+		let candidates = graph.find_cycles("uosmo", &CycleSearch::default(), &token);
+		let found: Vec<Vec<u64>> = candidates.iter().map(pool_ids).collect();
+		assert!(found.contains(&vec![1, 4]), "{:?}", found);
+		assert!(found.contains(&vec![4, 2, 3]), "{:?}", found);
+		assert!(candidates.windows(2).all(|pair| pair[0].rate >= pair[1].rate));
+		// never straight back through the pool just used
+		assert!(found.iter().all(|ids| ids.windows(2).all(|pair| pair[0] != pair[1])));
+
+		let search = CycleSearch { max_results: 1, ..CycleSearch::default() };
+		let best = graph.find_cycles("uosmo", &search, &token);
+		assert_eq!(best.len(), 1);
+		assert_eq!(pool_ids(&best[0]), found[0]);
+	}
+}
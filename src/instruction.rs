@@ -0,0 +1,344 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+	bpf_loader_upgradeable,
+	instruction::{AccountMeta, Instruction},
+	program_error::ProgramError,
+	pubkey::Pubkey,
+	system_program,
+};
+
+use crate::{
+	error::FeanorError,
+	state::{GateConfig, Vault},
+};
+
+// instructions understood by the feanor program
+//
+// the first byte of the instruction data is the variant tag, the rest is the
+// borsh encoding of the variant fields
+//
+// every vault and execution instruction but `Withdraw` ends with the Varda NFT
+// gate accounts:
+// `[]` gate config PDA, `[]` NFT token account of the owner and, when the gate
+// verifies collections, `[]` Metaplex metadata of the NFT
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Eq, PartialEq)]
+pub enum FeanorInstruction {
+	/// create the Varda Vault of the signer
+	///
+	/// accounts:
+	/// 0. `[signer, writable]` owner
+	/// 1. `[writable]` vault PDA
+	/// 2. `[]` system program
+	/// 3. `[]` gate config PDA
+	/// 4. `[]` NFT token account of the owner
+	/// 5. `[]` Metaplex metadata of the NFT, when the gate verifies collections
+	InitVault {
+		/// minimum profit a route must return, in basis points of the loan
+		min_profit_bps: u16,
+	},
+	/// move lamports from the signer into its vault
+	///
+	/// accounts:
+	/// 0. `[signer, writable]` owner
+	/// 1. `[writable]` vault PDA
+	/// 2. `[]` system program
+	/// 3. `[]` gate config PDA
+	/// 4. `[]` NFT token account of the owner
+	/// 5. `[]` Metaplex metadata of the NFT, when the gate verifies collections
+	Deposit {
+		amount: u64,
+	},
+	/// move lamports from the vault back to the signer
+	///
+	/// not gated, so an owner who sold its NFT can still empty its vault
+	///
+	/// accounts:
+	/// 0. `[signer, writable]` owner
+	/// 1. `[writable]` vault PDA
+	Withdraw {
+		amount: u64,
+	},
+	/// record the hash of the route the vault is allowed to execute
+	///
+	/// accounts:
+	/// 0. `[signer]` owner
+	/// 1. `[writable]` vault PDA
+	/// 2. `[]` gate config PDA
+	/// 3. `[]` NFT token account of the owner
+	/// 4. `[]` Metaplex metadata of the NFT, when the gate verifies collections
+	RegisterRoute {
+		route_hash: [u8; 32],
+	},
+	/// record the result of a route executed off-chain against the vault
+	///
+	/// bookkeeping only: the amounts are reported by the owner and no funds
+	/// move, so the program can only check them against the registered route
+	/// and the minimum profit, slippage being enforced by the swap messages
+	///
+	/// accounts:
+	/// 0. `[signer]` owner
+	/// 1. `[writable]` vault PDA
+	/// 2. `[]` gate config PDA
+	/// 3. `[]` NFT token account of the owner
+	/// 4. `[]` Metaplex metadata of the NFT, when the gate verifies collections
+	ExecuteRoute {
+		route_hash: [u8; 32],
+		amount_in: u64,
+		amount_out: u64,
+	},
+	/// create or update the Varda NFT gate config
+	///
+	/// accounts:
+	/// 0. `[signer, writable]` authority, the program upgrade authority on creation
+	/// 1. `[writable]` gate config PDA
+	/// 2. `[]` program data account of the feanor program
+	/// 3. `[]` system program
+	ConfigureGate {
+		collection_mint: Pubkey,
+		verify_collection: bool,
+	},
+}
+
+impl FeanorInstruction {
+	/// highest tag currently assigned to a variant
+	pub const MAX_TAG: u8 = 5;
+
+	/// decode instruction data, rejecting unknown tags explicitly
+	pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+		let (&tag, _) = input.split_first().ok_or(FeanorError::InvalidInstruction)?;
+		if tag > Self::MAX_TAG {
+			return Err(FeanorError::UnknownInstruction.into());
+		}
+		Self::try_from_slice(input).map_err(|_| FeanorError::InvalidInstruction.into())
+	}
+
+	/// encode the instruction into its on-wire form
+	pub fn pack(&self) -> Vec<u8> {
+		borsh::to_vec(self).expect("instruction serialization cannot fail")
+	}
+}
+
+// instruction builders for clients
+
+/// gate accounts for an owner holding the NFT in `nft_token_account`
+///
+/// `nft_metadata` must be given when the gate verifies collections
+pub fn gate_accounts(
+	program_id: &Pubkey,
+	nft_token_account: &Pubkey,
+	nft_metadata: Option<&Pubkey>,
+	) -> Vec<AccountMeta> {
+	let (gate, _) = GateConfig::find_address(program_id);
+	let mut accounts = vec![
+		AccountMeta::new_readonly(gate, false),
+		AccountMeta::new_readonly(*nft_token_account, false),
+	];
+	if let Some(metadata) = nft_metadata {
+		accounts.push(AccountMeta::new_readonly(*metadata, false));
+	}
+	accounts
+}
+
+pub fn init_vault(
+	program_id: &Pubkey,
+	owner: &Pubkey,
+	nft_token_account: &Pubkey,
+	nft_metadata: Option<&Pubkey>,
+	min_profit_bps: u16,
+	) -> Instruction {
+	let (vault, _) = Vault::find_address(program_id, owner);
+	let mut accounts = vec![
+		AccountMeta::new(*owner, true),
+		AccountMeta::new(vault, false),
+		AccountMeta::new_readonly(system_program::id(), false),
+	];
+	accounts.extend(gate_accounts(program_id, nft_token_account, nft_metadata));
+	Instruction {
+		program_id: *program_id,
+		accounts,
+		data: FeanorInstruction::InitVault { min_profit_bps }.pack(),
+	}
+}
+
+pub fn deposit(
+	program_id: &Pubkey,
+	owner: &Pubkey,
+	nft_token_account: &Pubkey,
+	nft_metadata: Option<&Pubkey>,
+	amount: u64,
+	) -> Instruction {
+	let (vault, _) = Vault::find_address(program_id, owner);
+	let mut accounts = vec![
+		AccountMeta::new(*owner, true),
+		AccountMeta::new(vault, false),
+		AccountMeta::new_readonly(system_program::id(), false),
+	];
+	accounts.extend(gate_accounts(program_id, nft_token_account, nft_metadata));
+	Instruction {
+		program_id: *program_id,
+		accounts,
+		data: FeanorInstruction::Deposit { amount }.pack(),
+	}
+}
+
+pub fn withdraw(
+	program_id: &Pubkey,
+	owner: &Pubkey,
+	amount: u64,
+	) -> Instruction {
+	let (vault, _) = Vault::find_address(program_id, owner);
+	Instruction {
+		program_id: *program_id,
+		accounts: vec![
+			AccountMeta::new(*owner, true),
+			AccountMeta::new(vault, false),
+		],
+		data: FeanorInstruction::Withdraw { amount }.pack(),
+	}
+}
+
+pub fn register_route(
+	program_id: &Pubkey,
+	owner: &Pubkey,
+	nft_token_account: &Pubkey,
+	nft_metadata: Option<&Pubkey>,
+	route_hash: [u8; 32],
+	) -> Instruction {
+	let (vault, _) = Vault::find_address(program_id, owner);
+	let mut accounts = vec![
+		AccountMeta::new_readonly(*owner, true),
+		AccountMeta::new(vault, false),
+	];
+	accounts.extend(gate_accounts(program_id, nft_token_account, nft_metadata));
+	Instruction {
+		program_id: *program_id,
+		accounts,
+		data: FeanorInstruction::RegisterRoute { route_hash }.pack(),
+	}
+}
+
+pub fn execute_route(
+	program_id: &Pubkey,
+	owner: &Pubkey,
+	nft_token_account: &Pubkey,
+	nft_metadata: Option<&Pubkey>,
+	route_hash: [u8; 32],
+	amount_in: u64,
+	amount_out: u64,
+	) -> Instruction {
+	let (vault, _) = Vault::find_address(program_id, owner);
+	let mut accounts = vec![
+		AccountMeta::new_readonly(*owner, true),
+		AccountMeta::new(vault, false),
+	];
+	accounts.extend(gate_accounts(program_id, nft_token_account, nft_metadata));
+	Instruction {
+		program_id: *program_id,
+		accounts,
+		data: FeanorInstruction::ExecuteRoute { route_hash, amount_in, amount_out }.pack(),
+	}
+}
+
+pub fn configure_gate(
+	program_id: &Pubkey,
+	authority: &Pubkey,
+	collection_mint: &Pubkey,
+	verify_collection: bool,
+	) -> Instruction {
+	let (gate, _) = GateConfig::find_address(program_id);
+	let (program_data, _) =
+		Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+	Instruction {
+		program_id: *program_id,
+		accounts: vec![
+			AccountMeta::new(*authority, true),
+			AccountMeta::new(gate, false),
+			AccountMeta::new_readonly(program_data, false),
+			AccountMeta::new_readonly(system_program::id(), false),
+		],
+		data: FeanorInstruction::ConfigureGate {
+			collection_mint: *collection_mint,
+			verify_collection,
+		}
+		.pack(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn every_variant() -> Vec<FeanorInstruction> {
+		vec![
+			FeanorInstruction::InitVault { min_profit_bps: 25 },
+			FeanorInstruction::Deposit { amount: 1_000_000 },
+			FeanorInstruction::Withdraw { amount: u64::MAX },
+			FeanorInstruction::RegisterRoute { route_hash: [7; 32] },
+			FeanorInstruction::ExecuteRoute {
+				route_hash: [9; 32],
+				amount_in: 100,
+				amount_out: 101,
+			},
+			FeanorInstruction::ConfigureGate {
+				collection_mint: Pubkey::new_from_array([3; 32]),
+				verify_collection: true,
+			},
+		]
+	}
+
+	#[test]
+	fn unpacks_what_it_packs() {
+		for (tag, instruction) in every_variant().into_iter().enumerate() {
+			let data = instruction.pack();
+			assert_eq!(data[0] as usize, tag);
+			assert_eq!(FeanorInstruction::unpack(&data).unwrap(), instruction);
+		}
+		assert_eq!(every_variant().len(), FeanorInstruction::MAX_TAG as usize + 1);
+	}
+
+	#[test]
+	fn packs_fields_little_endian_after_the_tag() {
+		assert_eq!(FeanorInstruction::InitVault { min_profit_bps: 0x0102 }.pack(), vec![0, 0x02, 0x01]);
+		assert_eq!(
+			FeanorInstruction::Deposit { amount: 5 }.pack(),
+			vec![1, 5, 0, 0, 0, 0, 0, 0, 0]
+		);
+	}
+
+	#[test]
+	fn rejects_unknown_tags_and_malformed_data() {
+		let unknown = ProgramError::from(FeanorError::UnknownInstruction);
+		let invalid = ProgramError::from(FeanorError::InvalidInstruction);
+		assert_eq!(FeanorInstruction::unpack(&[FeanorInstruction::MAX_TAG + 1]), Err(unknown.clone()));
+		assert_eq!(FeanorInstruction::unpack(&[0xff, 0, 0]), Err(unknown));
+		assert_eq!(FeanorInstruction::unpack(&[]), Err(invalid.clone()));
+		// truncated and trailing bytes
+		assert_eq!(FeanorInstruction::unpack(&[1, 5, 0, 0]), Err(invalid.clone()));
+		let mut data = FeanorInstruction::Deposit { amount: 5 }.pack();
+		data.push(0);
+		assert_eq!(FeanorInstruction::unpack(&data), Err(invalid.clone()));
+		// booleans other than 0 and 1
+		let mut data = every_variant()[5].pack();
+		*data.last_mut().unwrap() = 2;
+		assert_eq!(FeanorInstruction::unpack(&data), Err(invalid));
+	}
+
+	#[test]
+	fn builders_append_the_gate_accounts_but_to_withdraw() {
+		let (program_id, owner, token) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+		let metadata = Pubkey::new_unique();
+		let (gate, _) = GateConfig::find_address(&program_id);
+		let keys = |instruction: Instruction| -> Vec<Pubkey> {
+			instruction.accounts.iter().map(|account| account.pubkey).collect()
+		};
+
+		let deposit = keys(deposit(&program_id, &owner, &token, Some(&metadata), 1));
+		assert_eq!(deposit[3..], [gate, token, metadata]);
+		let register = keys(register_route(&program_id, &owner, &token, None, [0; 32]));
+		assert_eq!(register[2..], [gate, token]);
+
+		let (vault, _) = Vault::find_address(&program_id, &owner);
+		assert_eq!(keys(withdraw(&program_id, &owner, 1)), vec![owner, vault]);
+	}
+}
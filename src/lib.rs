@@ -1,28 +1,83 @@
+pub mod amm;
+pub mod denom;
+pub mod error;
+pub mod feeds;
+pub mod flash_loan;
+pub mod gate;
+pub mod graph;
+pub mod instruction;
+pub mod math;
+pub mod optimizer;
+pub mod poolmanager;
+pub mod processor;
+pub mod proto;
+pub mod registry;
+pub mod route;
+#[cfg(not(target_os = "solana"))]
+pub mod signer;
+pub mod state;
+pub mod tx;
+
 use solana_program::{
 	account_info::AccountInfo,
-	entrypoint,
 	entrypoint::ProgramResult,
+	program_error::PrintProgramError,
 	pubkey::Pubkey,
-	msg,
 };
 
+use crate::{error::FeanorError, processor::Processor};
+
 // declare and export the program entrypoint
 
-entrypoint!(process_instruction);
+#[cfg(not(feature = "no-entrypoint"))]
+solana_program::entrypoint!(process_instruction);
 
 // entrypoint implementation
 
-pub_fn process_instruction(
+pub fn process_instruction(
 	program_id: &Pubkey,
 	accounts: &[AccountInfo],
-	instruction_data: &[u8]	
-	)-> ProgramResult {
+	instruction_data: &[u8],
+	) -> ProgramResult {
+
+	// decode the instruction and hand it to the processor
+
+	if let Err(error) = Processor::process(program_id, accounts, instruction_data) {
+		// log the readable form of our own errors before failing
+		error.print::<FeanorError>();
+		return Err(error);
+	}
+	Ok(())
+	}
 
-	// log a message to the blockchain
+#[cfg(test)]
+mod tests {
+	use solana_program::program_error::ProgramError;
 
-	msg!("feanor!");
+	use super::*;
+
+	#[test]
+	fn rejects_undecodable_instructions_before_reading_accounts() {
+		let program_id = Pubkey::new_unique();
+		assert_eq!(
+			process_instruction(&program_id, &[], &[]),
+			Err(ProgramError::Custom(FeanorError::InvalidInstruction as u32))
+		);
+		assert_eq!(
+			process_instruction(&program_id, &[], &[0xff]),
+			Err(ProgramError::Custom(FeanorError::UnknownInstruction as u32))
+		);
+		// a known tag whose fields are cut short
+		assert_eq!(
+			process_instruction(&program_id, &[], &[1, 0, 0]),
+			Err(ProgramError::Custom(FeanorError::InvalidInstruction as u32))
+		);
+	}
 
-	// gracefully exit the program
-	Ok(()
-)
+	#[test]
+	fn asks_for_the_accounts_of_a_decoded_instruction() {
+		let program_id = Pubkey::new_unique();
+		let data = instruction::FeanorInstruction::Withdraw { amount: 1 }.pack();
+		assert_eq!(process_instruction(&program_id, &[], &data), Err(ProgramError::NotEnoughAccountKeys));
 	}
+}
@@ -0,0 +1,494 @@
+use std::{fmt, str::FromStr};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::FeanorError;
+
+// fixed point decimal math shared by the program and the off-chain bot
+//
+// values are unsigned with 18 fractional digits, like cosmwasm `Decimal`, and
+// every lossy operation takes an explicit rounding mode so amounts computed
+// here match the BigNumber.js results of the dashboard digit for digit
+
+const DECIMAL_FRACTIONAL: u128 = 1_000_000_000_000_000_000;
+
+/// how to resolve the digits dropped by a lossy operation
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Rounding {
+	/// towards zero, BigNumber `ROUND_DOWN`
+	Down,
+	/// away from zero, BigNumber `ROUND_UP`
+	Up,
+	/// to nearest, ties away from zero, BigNumber `ROUND_HALF_UP`
+	HalfUp,
+	/// to nearest, ties to even, BigNumber `ROUND_HALF_EVEN`
+	HalfEven,
+}
+
+/// unsigned fixed point number with 18 fractional digits
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Decimal(u128);
+
+impl Decimal {
+	pub const DECIMAL_PLACES: u32 = 18;
+	pub const ZERO: Decimal = Decimal(0);
+	pub const ONE: Decimal = Decimal(DECIMAL_FRACTIONAL);
+	pub const MAX: Decimal = Decimal(u128::MAX);
+
+	/// decimal from its raw value, in units of 10^-18
+	pub const fn raw(atomics: u128) -> Self {
+		Decimal(atomics)
+	}
+
+	/// raw value in units of 10^-18
+	pub const fn atomics(&self) -> u128 {
+		self.0
+	}
+
+	pub fn from_int(value: u128) -> Result<Self, FeanorError> {
+		value
+			.checked_mul(DECIMAL_FRACTIONAL)
+			.map(Decimal)
+			.ok_or(FeanorError::MathOverflow)
+	}
+
+	/// `value` percent, e.g. `percent(50)` is 0.5
+	pub const fn percent(value: u64) -> Self {
+		Decimal(value as u128 * 10_000_000_000_000_000)
+	}
+
+	/// `value` basis points, e.g. `bps(30)` is 0.003
+	pub const fn bps(value: u64) -> Self {
+		Decimal(value as u128 * 100_000_000_000_000)
+	}
+
+	/// `numerator / denominator`
+	pub fn from_ratio(numerator: u128, denominator: u128, rounding: Rounding) -> Result<Self, FeanorError> {
+		mul_div(numerator, DECIMAL_FRACTIONAL, denominator, rounding)
+			.map(Decimal)
+			.ok_or(FeanorError::MathOverflow)
+	}
+
+	/// decimal from an integer amount with `decimal_places` digits, e.g. `(1_500_000, 6)` is 1.5
+	pub fn from_atomics(atomics: u128, decimal_places: u32, rounding: Rounding) -> Result<Self, FeanorError> {
+		Decimal(atomics).shifted_by(Self::DECIMAL_PLACES as i32 - decimal_places as i32, rounding)
+	}
+
+	/// integer amount with `decimal_places` digits, BigNumber `shiftedBy(decimals).integerValue(rounding)`
+	pub fn to_atomics(&self, decimal_places: u32, rounding: Rounding) -> Result<u128, FeanorError> {
+		let scale = pow10(decimal_places)?;
+		mul_div(self.0, scale, DECIMAL_FRACTIONAL, rounding).ok_or(FeanorError::MathOverflow)
+	}
+
+	/// multiply by 10^places, BigNumber `shiftedBy(places)`
+	pub fn shifted_by(&self, places: i32, rounding: Rounding) -> Result<Self, FeanorError> {
+		let scale = pow10(places.unsigned_abs())?;
+		let atomics = if places >= 0 {
+			self.0.checked_mul(scale)
+		} else {
+			mul_div(self.0, 1, scale, rounding)
+		};
+		atomics.map(Decimal).ok_or(FeanorError::MathOverflow)
+	}
+
+	pub fn is_zero(&self) -> bool {
+		self.0 == 0
+	}
+
+	pub fn checked_add(self, other: Self) -> Result<Self, FeanorError> {
+		self.0.checked_add(other.0).map(Decimal).ok_or(FeanorError::MathOverflow)
+	}
+
+	pub fn checked_sub(self, other: Self) -> Result<Self, FeanorError> {
+		self.0.checked_sub(other.0).map(Decimal).ok_or(FeanorError::MathOverflow)
+	}
+
+	/// `self * other`, rounded down
+	pub fn checked_mul(self, other: Self) -> Result<Self, FeanorError> {
+		self.mul_rounded(other, Rounding::Down)
+	}
+
+	pub fn mul_rounded(self, other: Self, rounding: Rounding) -> Result<Self, FeanorError> {
+		mul_div(self.0, other.0, DECIMAL_FRACTIONAL, rounding)
+			.map(Decimal)
+			.ok_or(FeanorError::MathOverflow)
+	}
+
+	/// `self / other`, rounded down
+	pub fn checked_div(self, other: Self) -> Result<Self, FeanorError> {
+		self.div_rounded(other, Rounding::Down)
+	}
+
+	pub fn div_rounded(self, other: Self, rounding: Rounding) -> Result<Self, FeanorError> {
+		mul_div(self.0, DECIMAL_FRACTIONAL, other.0, rounding)
+			.map(Decimal)
+			.ok_or(FeanorError::MathOverflow)
+	}
+
+	/// `1 / self`, rounded down
+	pub fn inv(self) -> Result<Self, FeanorError> {
+		Decimal::ONE.checked_div(self)
+	}
+
+	/// `self^exp` by repeated squaring, each product rounded down
+	pub fn checked_pow(self, mut exp: u32) -> Result<Self, FeanorError> {
+		let mut base = self;
+		let mut result = Decimal::ONE;
+		while exp > 0 {
+			if exp & 1 == 1 {
+				result = result.checked_mul(base)?;
+			}
+			exp >>= 1;
+			if exp > 0 {
+				base = base.checked_mul(base)?;
+			}
+		}
+		Ok(result)
+	}
+
+	/// `self^exp` for a fractional exponent, following the osmomath `Pow`
+	///
+	/// the integer part of `exp` is applied exactly and the fractional part with a
+	/// binomial series that stops once terms fall below 10^-8; bases of two or more
+	/// are inverted first since the series only converges on (0, 2)
+	pub fn pow_frac(self, exp: Decimal) -> Result<Self, FeanorError> {
+		if self.is_zero() {
+			return if exp.is_zero() { Ok(Decimal::ONE) } else { Ok(Decimal::ZERO) };
+		}
+		if self >= Decimal::from_int(2)? {
+			return self.inv()?.pow_frac(exp)?.inv();
+		}
+		let whole = exp.floor();
+		let fraction = exp.checked_sub(whole)?;
+		let whole_exp = u32::try_from(whole.0 / DECIMAL_FRACTIONAL).map_err(|_| FeanorError::MathOverflow)?;
+		let whole_pow = self.checked_pow(whole_exp)?;
+		if fraction.is_zero() {
+			return Ok(whole_pow);
+		}
+		whole_pow.checked_mul(self.pow_approx(fraction)?)
+	}
+
+	// binomial series of (1 + x)^exp with x = self - 1, for 0 < self < 2 and 0 < exp < 1
+
+	fn pow_approx(self, exp: Decimal) -> Result<Self, FeanorError> {
+		const PRECISION: Decimal = Decimal(10_000_000_000);
+		let (x, x_negative) = abs_diff(self, Decimal::ONE);
+		let mut term = Decimal::ONE;
+		let mut sum = Decimal::ONE;
+		let mut negative = false;
+		let mut k: u128 = 1;
+		while term >= PRECISION {
+			let (c, c_negative) = abs_diff(exp, Decimal::from_int(k - 1)?);
+			term = term.checked_mul(c.checked_mul(x)?)?.checked_div(Decimal::from_int(k)?)?;
+			if term.is_zero() {
+				break;
+			}
+			if x_negative {
+				negative = !negative;
+			}
+			if c_negative {
+				negative = !negative;
+			}
+			sum = if negative { sum.checked_sub(term)? } else { sum.checked_add(term)? };
+			k += 1;
+		}
+		Ok(sum)
+	}
+
+	/// square root, rounded down
+	pub fn sqrt(&self) -> Self {
+		// sqrt(atomics * 10^18) is exact to 18 digits; large values scale by less
+		// and make up the difference outside the root
+		(0..=Self::DECIMAL_PLACES / 2)
+			.rev()
+			.find_map(|half| {
+				let scaled = self.0.checked_mul(10u128.pow(half * 2))?;
+				Some(Decimal(isqrt(scaled) * 10u128.pow(Self::DECIMAL_PLACES / 2 - half)))
+			})
+			.expect("atomics times 10^0 always fits")
+	}
+
+	/// apply the decimal to an integer amount, e.g. a price or a fee to a token amount
+	pub fn mul_int(self, amount: u128, rounding: Rounding) -> Result<u128, FeanorError> {
+		mul_div(amount, self.0, DECIMAL_FRACTIONAL, rounding).ok_or(FeanorError::MathOverflow)
+	}
+
+	/// divide an integer amount by the decimal
+	pub fn div_int(self, amount: u128, rounding: Rounding) -> Result<u128, FeanorError> {
+		mul_div(amount, DECIMAL_FRACTIONAL, self.0, rounding).ok_or(FeanorError::MathOverflow)
+	}
+
+	pub fn floor(&self) -> Self {
+		Decimal(self.0 - self.0 % DECIMAL_FRACTIONAL)
+	}
+
+	pub fn ceil(&self) -> Result<Self, FeanorError> {
+		self.round_dp(0, Rounding::Up)
+	}
+
+	/// round to `places` fractional digits
+	pub fn round_dp(&self, places: u32, rounding: Rounding) -> Result<Self, FeanorError> {
+		if places >= Self::DECIMAL_PLACES {
+			return Ok(*self);
+		}
+		let unit = pow10(Self::DECIMAL_PLACES - places)?;
+		mul_div(self.0, 1, unit, rounding)
+			.and_then(|units| units.checked_mul(unit))
+			.map(Decimal)
+			.ok_or(FeanorError::MathOverflow)
+	}
+
+	/// string with exactly `places` fractional digits, BigNumber `toFixed(places)`
+	pub fn to_fixed(&self, places: u32, rounding: Rounding) -> Result<String, FeanorError> {
+		let rounded = self.round_dp(places, rounding)?;
+		let whole = rounded.0 / DECIMAL_FRACTIONAL;
+		if places == 0 {
+			return Ok(whole.to_string());
+		}
+		let fraction = format!("{:018}", rounded.0 % DECIMAL_FRACTIONAL);
+		let digits = places.min(Self::DECIMAL_PLACES) as usize;
+		let padding = places as usize - digits;
+		Ok(format!("{}.{}{}", whole, &fraction[..digits], "0".repeat(padding)))
+	}
+}
+
+impl fmt::Display for Decimal {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let whole = self.0 / DECIMAL_FRACTIONAL;
+		let fraction = self.0 % DECIMAL_FRACTIONAL;
+		if fraction == 0 {
+			return write!(f, "{}", whole);
+		}
+		let fraction = format!("{:018}", fraction);
+		write!(f, "{}.{}", whole, fraction.trim_end_matches('0'))
+	}
+}
+
+impl FromStr for Decimal {
+	type Err = FeanorError;
+
+	/// parse a plain decimal string such as "0.002000000000000000"
+	fn from_str(input: &str) -> Result<Self, Self::Err> {
+		let (whole, fraction) = input.split_once('.').unwrap_or((input, ""));
+		let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+		if whole.is_empty() || !is_digits(whole) || !is_digits(fraction) {
+			return Err(FeanorError::InvalidDecimal);
+		}
+		if fraction.len() > Self::DECIMAL_PLACES as usize {
+			return Err(FeanorError::InvalidDecimal);
+		}
+		let whole: u128 = whole.parse().map_err(|_| FeanorError::InvalidDecimal)?;
+		let mut atomics = whole.checked_mul(DECIMAL_FRACTIONAL).ok_or(FeanorError::MathOverflow)?;
+		if !fraction.is_empty() {
+			let digits: u128 = fraction.parse().map_err(|_| FeanorError::InvalidDecimal)?;
+			let scale = pow10(Self::DECIMAL_PLACES - fraction.len() as u32)?;
+			atomics = atomics.checked_add(digits * scale).ok_or(FeanorError::MathOverflow)?;
+		}
+		Ok(Decimal(atomics))
+	}
+}
+
+impl Serialize for Decimal {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(&self.to_string())
+	}
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let value = <String as Deserialize>::deserialize(deserializer)?;
+		value.parse().map_err(|_| de::Error::custom(format!("invalid decimal '{}'", value)))
+	}
+}
+
+/// `|a - b|` and whether `a - b` is negative
+fn abs_diff(a: Decimal, b: Decimal) -> (Decimal, bool) {
+	if a >= b {
+		(Decimal(a.0 - b.0), false)
+	} else {
+		(Decimal(b.0 - a.0), true)
+	}
+}
+
+/// integer square root, rounded down
+fn isqrt(value: u128) -> u128 {
+	if value < 2 {
+		return value;
+	}
+	// newton iteration from a power of two above the root
+	let mut x = 1u128 << ((128 - value.leading_zeros()) / 2 + 1);
+	loop {
+		let y = (x + value / x) / 2;
+		if y >= x {
+			return x;
+		}
+		x = y;
+	}
+}
+
+fn pow10(exp: u32) -> Result<u128, FeanorError> {
+	10u128.checked_pow(exp).ok_or(FeanorError::MathOverflow)
+}
+
+/// `a * b / c` with a 256 bit intermediate product, `None` on overflow or division by zero
+pub fn mul_div(a: u128, b: u128, c: u128, rounding: Rounding) -> Option<u128> {
+	if c == 0 {
+		return None;
+	}
+	let (hi, lo) = full_mul(a, b);
+	// the quotient only fits in 128 bits when the high half is below the divisor
+	if hi >= c {
+		return None;
+	}
+
+	// binary long division of hi:lo by c
+	let mut quotient = 0u128;
+	let mut remainder = hi;
+	for bit in (0..128).rev() {
+		let carry = remainder >> 127;
+		remainder = (remainder << 1) | ((lo >> bit) & 1);
+		if carry == 1 || remainder >= c {
+			remainder = remainder.wrapping_sub(c);
+			quotient |= 1 << bit;
+		}
+	}
+
+	let round_up = match rounding {
+		Rounding::Down => false,
+		Rounding::Up => remainder > 0,
+		Rounding::HalfUp => remainder >= c - remainder,
+		Rounding::HalfEven => {
+			let half = c - remainder;
+			remainder > half || (remainder == half && quotient & 1 == 1)
+		}
+	};
+	if round_up {
+		quotient.checked_add(1)
+	} else {
+		Some(quotient)
+	}
+}
+
+// 128 x 128 -> 256 bit multiplication, returned as (high, low) halves
+
+fn full_mul(a: u128, b: u128) -> (u128, u128) {
+	const MASK: u128 = u64::MAX as u128;
+	let (a_hi, a_lo) = (a >> 64, a & MASK);
+	let (b_hi, b_lo) = (b >> 64, b & MASK);
+
+	let lo_lo = a_lo * b_lo;
+	let lo_hi = a_lo * b_hi;
+	let hi_lo = a_hi * b_lo;
+	let hi_hi = a_hi * b_hi;
+
+	let middle = (lo_lo >> 64) + (lo_hi & MASK) + (hi_lo & MASK);
+	let low = (lo_lo & MASK) | (middle << 64);
+	let high = hi_hi + (lo_hi >> 64) + (hi_lo >> 64) + (middle >> 64);
+	(high, low)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn dec(value: &str) -> Decimal {
+		value.parse().unwrap()
+	}
+
+	#[test]
+	fn mul_div_uses_a_256_bit_intermediate() {
+		assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX, Rounding::Down), Some(u128::MAX));
+		let (a, b, c) = (2u128.pow(127) + 12_345, 10u128.pow(30) + 7, 10u128.pow(31) + 3);
+		let quotient = 17_014_118_346_046_923_173_168_730_371_702_406_400;
+		assert_eq!(mul_div(a, b, c, Rounding::Down), Some(quotient));
+		assert_eq!(mul_div(a, b, c, Rounding::Up), Some(quotient + 1));
+		// the remainder is below half the divisor
+		assert_eq!(mul_div(a, b, c, Rounding::HalfUp), Some(quotient));
+		assert_eq!(mul_div(u128::MAX, 2, 1, Rounding::Down), None);
+		assert_eq!(mul_div(1, 1, 0, Rounding::Down), None);
+	}
+
+	#[test]
+	fn mul_div_rounding_modes() {
+		// (a, c, down, up, half up, half even) for a / c
+		let cases = [
+			(7, 2, 3, 4, 4, 4),
+			(5, 2, 2, 3, 3, 2),
+			(9, 4, 2, 3, 2, 2),
+			(10, 4, 2, 3, 3, 2),
+			(11, 4, 2, 3, 3, 3),
+			(8, 4, 2, 2, 2, 2),
+		];
+		for (a, c, down, up, half_up, half_even) in cases {
+			assert_eq!(mul_div(a, 1, c, Rounding::Down), Some(down), "{}/{}", a, c);
+			assert_eq!(mul_div(a, 1, c, Rounding::Up), Some(up), "{}/{}", a, c);
+			assert_eq!(mul_div(a, 1, c, Rounding::HalfUp), Some(half_up), "{}/{}", a, c);
+			assert_eq!(mul_div(a, 1, c, Rounding::HalfEven), Some(half_even), "{}/{}", a, c);
+		}
+	}
+
+	#[test]
+	fn matches_bignumber_shifted_by_and_to_fixed() {
+		// new BigNumber("1.23456789").shiftedBy(6).integerValue(mode)
+		let value = dec("1.23456789");
+		assert_eq!(value.to_atomics(6, Rounding::Down), Ok(1_234_567));
+		assert_eq!(value.to_atomics(6, Rounding::Up), Ok(1_234_568));
+		assert_eq!(value.to_atomics(6, Rounding::HalfUp), Ok(1_234_568));
+		assert_eq!(Decimal::from_atomics(1_500_000, 6, Rounding::Down), Ok(dec("1.5")));
+		assert_eq!(dec("0.000123").shifted_by(-2, Rounding::Down), Ok(dec("0.00000123")));
+
+		// new BigNumber(1).dividedBy(3).toFixed(18), the dashboard belief price format
+		let third = Decimal::from_ratio(1, 3, Rounding::Down).unwrap();
+		assert_eq!(third.to_fixed(18, Rounding::Down).unwrap(), "0.333333333333333333");
+		let two_thirds = Decimal::from_ratio(2, 3, Rounding::Down).unwrap();
+		assert_eq!(two_thirds.to_fixed(2, Rounding::HalfUp).unwrap(), "0.67");
+		assert_eq!(dec("2.5").to_fixed(0, Rounding::HalfEven).unwrap(), "2");
+		assert_eq!(dec("3.5").to_fixed(0, Rounding::HalfEven).unwrap(), "4");
+		assert_eq!(dec("0.005").to_fixed(18, Rounding::Down).unwrap(), "0.005000000000000000");
+	}
+
+	#[test]
+	fn parses_and_displays_chain_decimals() {
+		assert_eq!(dec("0.002000000000000000"), Decimal::bps(20));
+		assert_eq!(Decimal::bps(20).to_string(), "0.002");
+		assert_eq!(Decimal::from_int(42).unwrap().to_string(), "42");
+		assert_eq!("0.0000000000000000001".parse::<Decimal>(), Err(FeanorError::InvalidDecimal));
+		assert_eq!("-1".parse::<Decimal>(), Err(FeanorError::InvalidDecimal));
+		assert_eq!(".5".parse::<Decimal>(), Err(FeanorError::InvalidDecimal));
+	}
+
+	#[test]
+	fn sqrt_is_rounded_down() {
+		assert_eq!(dec("4").sqrt(), dec("2"));
+		assert_eq!(dec("2").sqrt(), dec("1.414213562373095048"));
+		assert_eq!(dec("0.1").sqrt(), dec("0.316227766016837933"));
+		assert_eq!(Decimal::raw(1).sqrt(), dec("0.000000001"));
+		assert_eq!(Decimal::from_int(10u128.pow(20)).unwrap().sqrt(), Decimal::from_int(10u128.pow(10)).unwrap());
+		// the largest values keep fewer digits but stay below the true root
+		let root = Decimal::MAX.sqrt();
+		assert!(root.checked_mul(root).unwrap() <= Decimal::MAX);
+		assert!(root > dec("18446744073.709551"));
+	}
+
+	#[test]
+	fn pow_frac_matches_known_values() {
+		// the series stops at terms below 10^-8, so the dropped tail is a few
+		// multiples of that when the base is far from one; inverted bases scale it
+		let tolerance = dec("0.0000001");
+		let cases = [
+			("0.5", "0.5", "0.707106781186547524"),
+			("1.5", "2.5", "2.755675960631075360"),
+			("1.01", "0.3", "1.002989559101323932"),
+			("3", "0.5", "1.732050807568877293"),
+		];
+		for (base, exp, expected) in cases {
+			let result = dec(base).pow_frac(dec(exp)).unwrap();
+			let (gap, _) = abs_diff(result, dec(expected));
+			assert!(gap <= tolerance, "{}^{} = {}, expected {}", base, exp, result, expected);
+		}
+		// whole exponents are exact
+		assert_eq!(dec("1.1").pow_frac(dec("3")), dec("1.1").checked_pow(3));
+		assert_eq!(Decimal::ZERO.pow_frac(Decimal::ZERO), Ok(Decimal::ONE));
+	}
+}
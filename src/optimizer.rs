@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	amm::PoolSet,
+	error::FeanorError,
+	math::{mul_div, Decimal, Rounding},
+	route::{Route, RouteEvaluation},
+};
+
+// flash loan size search
+//
+// the net profit of a route is concave in the loan size for the pool models
+// of `amm`, so a golden-section search over the integer loan amount finds the
+// size that maximizes it after swap fees and the flash loan premium
+
+/// 1 / golden ratio, scaled by 10^6
+const INVERSE_PHI_MICROS: u128 = 618_034;
+
+/// bounds and costs of the search
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct LoanSizing {
+	/// smallest loan considered, in base units of the loan denom
+	pub min_loan: u128,
+	/// largest loan the lender allows or the vault is willing to take
+	pub max_loan: u128,
+	/// premium charged by the flash loan provider on the borrowed amount
+	pub flash_loan_fee: Decimal,
+	/// the search stops once the bracket is this narrow
+	pub tolerance: u128,
+	/// evenly spaced samples reported in the profit curve
+	pub curve_points: usize,
+}
+
+impl LoanSizing {
+	pub fn new(max_loan: u128) -> Self {
+		LoanSizing {
+			min_loan: 1,
+			max_loan,
+			flash_loan_fee: Decimal::ZERO,
+			tolerance: 1,
+			curve_points: 20,
+		}
+	}
+}
+
+/// net profit of the route at one loan size
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct ProfitPoint {
+	pub amount_in: u128,
+	/// profit after the flash loan premium, `None` when the route cannot be filled
+	pub net_profit: Option<i128>,
+}
+
+/// best loan size found for a route
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct OptimalLoan {
+	pub amount_in: u128,
+	pub premium: u128,
+	/// profit after swap fees and the premium, in the loan denom
+	pub net_profit: i128,
+	pub evaluation: RouteEvaluation,
+	pub curve: Vec<ProfitPoint>,
+}
+
+impl OptimalLoan {
+	pub fn is_profitable(&self) -> bool {
+		self.net_profit > 0
+	}
+}
+
+/// loan size in `sizing` bounds maximizing the net profit of `route`
+pub fn optimize_loan(route: &Route, pools: &PoolSet, sizing: &LoanSizing) -> Result<OptimalLoan, FeanorError> {
+	if sizing.min_loan == 0 || sizing.min_loan > sizing.max_loan {
+		return Err(FeanorError::InvalidBounds);
+	}
+	let mut search = Search {
+		route,
+		pools,
+		sizing,
+		evaluated: BTreeMap::new(),
+	};
+
+	let mut low = sizing.min_loan;
+	let mut high = sizing.max_loan;
+	let tolerance = sizing.tolerance.max(2);
+	// on a handful of integers the golden points cross over and could cut the
+	// optimum out of the bracket, so what is left is scanned instead
+	let mut exhaustive = false;
+	while high - low > tolerance {
+		let step = mul_div(high - low, INVERSE_PHI_MICROS, 1_000_000, Rounding::Down)
+			.ok_or(FeanorError::MathOverflow)?;
+		let left = high - step;
+		let right = low + step;
+		if left >= right {
+			exhaustive = true;
+			break;
+		}
+		if search.profit(left) < search.profit(right) {
+			low = left;
+		} else {
+			high = right;
+		}
+	}
+
+	// settle on the best of what is left of the bracket
+	let candidates = if exhaustive {
+		(low..=high).collect()
+	} else {
+		vec![low, low + (high - low) / 2, high]
+	};
+	let mut best: Option<(u128, i128)> = None;
+	for amount in candidates {
+		if let Some(profit) = search.profit(amount) {
+			let better = match best {
+				Some((_, best_profit)) => profit > best_profit,
+				None => true,
+			};
+			if better {
+				best = Some((amount, profit));
+			}
+		}
+	}
+	let (amount_in, net_profit) = best.ok_or(FeanorError::InsufficientLiquidity)?;
+
+	let curve = (0..sizing.curve_points)
+		.map(|i| {
+			let span = sizing.max_loan - sizing.min_loan;
+			let denominator = sizing.curve_points.saturating_sub(1).max(1) as u128;
+			let amount = sizing.min_loan + mul_div(span, i as u128, denominator, Rounding::Down).unwrap_or(span);
+			ProfitPoint { amount_in: amount, net_profit: search.profit(amount) }
+		})
+		.collect();
+
+	let evaluation = route.evaluate(amount_in, pools)?;
+	Ok(OptimalLoan {
+		amount_in,
+		premium: premium(amount_in, sizing)?,
+		net_profit,
+		evaluation,
+		curve,
+	})
+}
+
+/// flash loan premium owed on `amount_in`, rounded up
+fn premium(amount_in: u128, sizing: &LoanSizing) -> Result<u128, FeanorError> {
+	sizing.flash_loan_fee.mul_int(amount_in, Rounding::Up)
+}
+
+// memoized evaluations of one search
+
+struct Search<'a> {
+	route: &'a Route,
+	pools: &'a PoolSet,
+	sizing: &'a LoanSizing,
+	evaluated: BTreeMap<u128, Option<i128>>,
+}
+
+impl Search<'_> {
+	/// net profit after the premium, `None` when the route cannot be filled at that size
+	fn profit(&mut self, amount_in: u128) -> Option<i128> {
+		if let Some(profit) = self.evaluated.get(&amount_in) {
+			return *profit;
+		}
+		let profit = self
+			.route
+			.evaluate(amount_in, self.pools)
+			.ok()
+			.zip(premium(amount_in, self.sizing).ok())
+			.and_then(|(evaluation, premium)| {
+				evaluation.net_profit.checked_sub(i128::try_from(premium).ok()?)
+			});
+		self.evaluated.insert(amount_in, profit);
+		profit
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		amm::{ConstantProduct, PoolAsset},
+		route::{RouteStep, RouteToken},
+	};
+
+	fn asset(denom: &str, reserve: u128) -> PoolAsset {
+		PoolAsset { denom: denom.into(), reserve, weight: 1 }
+	}
+
+	// uosmo -> uatom on a pool where atom is cheap, then back where it is dear
+	fn setup() -> (Route, PoolSet) {
+		setup_scaled(1)
+	}
+
+	fn setup_scaled(scale: u128) -> (Route, PoolSet) {
+		let (low, high) = (1_000 * scale, 1_100 * scale);
+		let mut pools = PoolSet::new();
+		pools.insert(1, ConstantProduct::new([asset("uosmo", low), asset("uatom", high)], Decimal::ZERO).unwrap());
+		pools.insert(2, ConstantProduct::new([asset("uatom", low), asset("uosmo", high)], Decimal::ZERO).unwrap());
+		let osmo = RouteToken::new("osmo", "uosmo", 6);
+		let atom = RouteToken::new("atom", "uatom", 6);
+		let route = Route {
+			from: osmo.clone(),
+			to: osmo.clone(),
+			steps: vec![
+				RouteStep::new(1, Decimal::ZERO, osmo.clone(), atom.clone(), "osmosis"),
+				RouteStep::new(2, Decimal::ZERO, atom, osmo, "osmosis"),
+			],
+		};
+		(route, pools)
+	}
+
+	fn brute_force(route: &Route, pools: &PoolSet, sizing: &LoanSizing) -> i128 {
+		(sizing.min_loan..=sizing.max_loan)
+			.filter_map(|amount| route.evaluate(amount, pools).ok())
+			.map(|evaluation| evaluation.net_profit - premium(evaluation.amount_in, sizing).unwrap() as i128)
+			.max()
+			.unwrap()
+	}
+
+	#[test]
+	fn finds_the_best_loan_size() {
+		let (route, pools) = setup();
+		let sizing = LoanSizing::new(900);
+		let loan = optimize_loan(&route, &pools, &sizing).unwrap();
+		assert_eq!(loan.net_profit, brute_force(&route, &pools, &sizing));
+		assert!(loan.is_profitable());
+		assert_eq!(loan.curve.len(), 20);
+	}
+
+	#[test]
+	fn narrow_brackets_are_scanned_exhaustively() {
+		let (route, pools) = setup();
+		let best = optimize_loan(&route, &pools, &LoanSizing::new(900)).unwrap().amount_in;
+		// every bracket of three to five integers around the optimum, wherever it sits
+		for span in 3..=4 {
+			for offset in 0..=span {
+				let mut sizing = LoanSizing::new(best - offset + span);
+				sizing.min_loan = best - offset;
+				let loan = optimize_loan(&route, &pools, &sizing).unwrap();
+				let expected = brute_force(&route, &pools, &sizing);
+				assert_eq!(loan.net_profit, expected, "{}..={}", sizing.min_loan, sizing.max_loan);
+			}
+		}
+	}
+
+	#[test]
+	fn the_premium_moves_the_optimum_down() {
+		// deep enough pools for the profit to change by more than a unit
+		// between neighbouring loan sizes
+		let (route, pools) = setup_scaled(100);
+		let free = optimize_loan(&route, &pools, &LoanSizing::new(20_000)).unwrap();
+		assert_eq!(free.premium, 0);
+
+		let mut sizing = LoanSizing::new(20_000);
+		sizing.flash_loan_fee = Decimal::percent(2);
+		let loan = optimize_loan(&route, &pools, &sizing).unwrap();
+		assert_eq!(loan.net_profit, brute_force(&route, &pools, &sizing));
+		assert!(loan.amount_in < free.amount_in, "{} vs {}", loan.amount_in, free.amount_in);
+		// the premium is rounded up and taken out of the swap profit
+		assert_eq!(loan.premium, (loan.amount_in * 2).div_ceil(100));
+		assert_eq!(loan.net_profit, loan.evaluation.net_profit - loan.premium as i128);
+		assert!(loan.net_profit < free.net_profit);
+
+		// a premium above the best swap margin leaves nothing to take
+		sizing.flash_loan_fee = Decimal::percent(25);
+		let loan = optimize_loan(&route, &pools, &sizing).unwrap();
+		assert!(!loan.is_profitable());
+		assert_eq!(loan.net_profit, brute_force(&route, &pools, &sizing));
+	}
+
+	#[test]
+	fn rejects_zero_and_inverted_bounds() {
+		let (route, pools) = setup();
+		let mut sizing = LoanSizing::new(900);
+		sizing.min_loan = 0;
+		assert_eq!(optimize_loan(&route, &pools, &sizing), Err(FeanorError::InvalidBounds));
+		sizing.min_loan = 901;
+		assert_eq!(optimize_loan(&route, &pools, &sizing), Err(FeanorError::InvalidBounds));
+		assert_eq!(optimize_loan(&route, &pools, &LoanSizing::new(0)), Err(FeanorError::InvalidBounds));
+	}
+}
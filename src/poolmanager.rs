@@ -0,0 +1,294 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+	error::FeanorError,
+	flash_loan::Coin,
+	math::{Decimal, Rounding},
+	proto::{self, Any, Message},
+	route::{Route, RouteEvaluation},
+};
+
+// Osmosis poolmanager multi-hop swap
+//
+// alternative to the flash loan builder: the whole route becomes a single
+// `MsgSwapExactAmountIn` executed natively by the chain, one route entry per
+// step, with no wasm execute nor CW20 allowance involved
+
+pub const MSG_SWAP_EXACT_AMOUNT_IN_TYPE_URL: &str = "/osmosis.poolmanager.v1beta1.MsgSwapExactAmountIn";
+pub const MSG_SWAP_EXACT_AMOUNT_IN_AMINO_TYPE: &str = "osmosis/poolmanager/swap-exact-amount-in";
+
+/// one hop of the swap: the pool and the denom it hands out
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct SwapAmountInRoute {
+	/// uint64 as a string, like the amino JSON of the chain
+	#[serde(with = "u64_string")]
+	pub pool_id: u64,
+	pub token_out_denom: String,
+}
+
+/// `osmosis.poolmanager.v1beta1.MsgSwapExactAmountIn`
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct MsgSwapExactAmountIn {
+	pub sender: String,
+	pub routes: Vec<SwapAmountInRoute>,
+	pub token_in: Coin,
+	pub token_out_min_amount: String,
+}
+
+/// amino JSON envelope signed by `SIGN_MODE_LEGACY_AMINO_JSON` wallets
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct AminoMsg<T> {
+	#[serde(rename = "type")]
+	pub type_: String,
+	pub value: T,
+}
+
+impl MsgSwapExactAmountIn {
+	/// swap of the whole route, accepting at most `slippage` less than the simulated out amount
+	pub fn from_route(
+		sender: &str,
+		route: &Route,
+		evaluation: &RouteEvaluation,
+		slippage: Decimal,
+		) -> Result<Self, FeanorError> {
+		route.validate()?;
+		if route.steps.len() != evaluation.steps.len() {
+			return Err(FeanorError::InvalidRoute);
+		}
+
+		let routes = route
+			.steps
+			.iter()
+			.zip(&evaluation.steps)
+			.map(|(step, simulated)| match step.pool_id() {
+				Some(pool_id) if pool_id == simulated.pool_id => Ok(SwapAmountInRoute {
+					pool_id,
+					token_out_denom: step.to_token.denom.clone(),
+				}),
+				_ => Err(FeanorError::InvalidRoute),
+			})
+			.collect::<Result<Vec<_>, _>>()?;
+
+		let token_out_min_amount = Decimal::ONE
+			.checked_sub(slippage)?
+			.mul_int(evaluation.amount_out, Rounding::Down)?;
+		if token_out_min_amount == 0 {
+			return Err(FeanorError::SlippageExceeded);
+		}
+
+		Ok(MsgSwapExactAmountIn {
+			sender: sender.to_string(),
+			routes,
+			token_in: Coin {
+				denom: route.from.denom.clone(),
+				amount: evaluation.amount_in.to_string(),
+			},
+			token_out_min_amount: token_out_min_amount.to_string(),
+		})
+	}
+
+	pub fn to_any(&self) -> Any {
+		Any::pack(MSG_SWAP_EXACT_AMOUNT_IN_TYPE_URL, self)
+	}
+
+	pub fn to_amino(&self) -> AminoMsg<&Self> {
+		AminoMsg {
+			type_: MSG_SWAP_EXACT_AMOUNT_IN_AMINO_TYPE.to_string(),
+			value: self,
+		}
+	}
+
+	/// amino JSON with the keys of every object sorted, as signed in
+	/// `SIGN_MODE_LEGACY_AMINO_JSON` whatever the field order of the structs
+	pub fn to_amino_json(&self) -> String {
+		let value = serde_json::to_value(self.to_amino()).expect("amino serialization cannot fail");
+		serde_json::to_string(&sort_keys(value)).expect("amino serialization cannot fail")
+	}
+}
+
+/// `value` with the keys of every object in lexicographic order, also when
+/// serde_json keeps the insertion order
+pub fn sort_keys(value: Value) -> Value {
+	match value {
+		Value::Object(map) => {
+			let mut entries: Vec<(String, Value)> = map.into_iter().collect();
+			entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+			Value::Object(entries.into_iter().map(|(key, value)| (key, sort_keys(value))).collect())
+		}
+		Value::Array(values) => Value::Array(values.into_iter().map(sort_keys).collect()),
+		other => other,
+	}
+}
+
+impl Message for SwapAmountInRoute {
+	fn encode_raw(&self, buf: &mut Vec<u8>) {
+		proto::uint64(1, self.pool_id, buf);
+		proto::string(2, &self.token_out_denom, buf);
+	}
+}
+
+impl Message for Coin {
+	fn encode_raw(&self, buf: &mut Vec<u8>) {
+		proto::string(1, &self.denom, buf);
+		proto::string(2, &self.amount, buf);
+	}
+}
+
+impl Message for MsgSwapExactAmountIn {
+	fn encode_raw(&self, buf: &mut Vec<u8>) {
+		proto::string(1, &self.sender, buf);
+		proto::repeated(2, &self.routes, buf);
+		proto::message(3, &self.token_in, buf);
+		proto::string(4, &self.token_out_min_amount, buf);
+	}
+}
+
+mod u64_string {
+	use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+	pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(value)
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+		String::deserialize(deserializer)?.parse().map_err(D::Error::custom)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::json;
+
+	use super::*;
+	use crate::route::{RouteStep, RouteToken, StepEvaluation};
+
+	const SENDER: &str = "osmo1qqqsyqcyq5rqwzqfpg9scrgwpugpzysntdz28t";
+
+	// the message below encoded field by field from the poolmanager protos, and
+	// its amino form written out from the amino names of the chain, keys sorted
+	const PROTOBUF: &str = concat!(
+		"0a2b6f736d6f31717171737971637971357271777a71667067397363726777707567707a79736e74647a323874", // sender
+		"1209080112057561746f6d",               // routes[0]
+		"120a08a6051205756f736d6f",             // routes[1]
+		"1a100a05756f736d6f120731303030303030", // token_in
+		"2206393930303030",                     // token_out_min_amount
+	);
+
+	const AMINO_JSON: &str = concat!(
+		r#"{"type":"osmosis/poolmanager/swap-exact-amount-in","value":{"#,
+		r#""routes":[{"pool_id":"1","token_out_denom":"uatom"},{"pool_id":"678","token_out_denom":"uosmo"}],"#,
+		r#""sender":"osmo1qqqsyqcyq5rqwzqfpg9scrgwpugpzysntdz28t","#,
+		r#""token_in":{"amount":"1000000","denom":"uosmo"},"#,
+		r#""token_out_min_amount":"990000"}}"#,
+	);
+
+	fn hex(value: &str) -> Vec<u8> {
+		(0..value.len())
+			.step_by(2)
+			.map(|i| u8::from_str_radix(&value[i..i + 2], 16).unwrap())
+			.collect()
+	}
+
+	fn simulated(pool_id: u64, denom_in: &str, denom_out: &str, amount_in: u128, amount_out: u128) -> StepEvaluation {
+		StepEvaluation {
+			pool_id,
+			denom_in: denom_in.into(),
+			denom_out: denom_out.into(),
+			amount_in,
+			amount_out,
+			fee_paid: 0,
+			price_impact: Decimal::ZERO,
+		}
+	}
+
+	// uosmo -> uatom on pool 1, back on pool 678, expected to return 1 000 000
+	fn setup() -> (Route, RouteEvaluation) {
+		let osmo = RouteToken::new("osmo", "uosmo", 6);
+		let atom = RouteToken::new("atom", "uatom", 6);
+		let route = Route {
+			from: osmo.clone(),
+			to: osmo.clone(),
+			steps: vec![
+				RouteStep::new(1, Decimal::ZERO, osmo.clone(), atom.clone(), "osmosis"),
+				RouteStep::new(678, Decimal::ZERO, atom, osmo, "osmosis"),
+			],
+		};
+		let evaluation = RouteEvaluation {
+			amount_in: 1_000_000,
+			amount_out: 1_000_000,
+			steps: vec![
+				simulated(1, "uosmo", "uatom", 1_000_000, 95_000),
+				simulated(678, "uatom", "uosmo", 95_000, 1_000_000),
+			],
+			net_profit: 0,
+		};
+		(route, evaluation)
+	}
+
+	fn msg() -> MsgSwapExactAmountIn {
+		let (route, evaluation) = setup();
+		MsgSwapExactAmountIn::from_route(SENDER, &route, &evaluation, Decimal::percent(1)).unwrap()
+	}
+
+	#[test]
+	fn builds_one_route_entry_per_step() {
+		let msg = msg();
+		assert_eq!(
+			msg.routes,
+			vec![
+				SwapAmountInRoute { pool_id: 1, token_out_denom: "uatom".into() },
+				SwapAmountInRoute { pool_id: 678, token_out_denom: "uosmo".into() },
+			]
+		);
+		assert_eq!(msg.token_in, Coin { denom: "uosmo".into(), amount: "1000000".into() });
+		assert_eq!(msg.token_out_min_amount, "990000");
+	}
+
+	#[test]
+	fn matches_the_golden_protobuf_bytes() {
+		let msg = msg();
+		assert_eq!(msg.encode_to_vec(), hex(PROTOBUF));
+		let any = msg.to_any();
+		assert_eq!(any.type_url, MSG_SWAP_EXACT_AMOUNT_IN_TYPE_URL);
+		assert_eq!(any.value, hex(PROTOBUF));
+	}
+
+	#[test]
+	fn matches_the_golden_amino_json() {
+		let amino = msg().to_amino_json();
+		assert_eq!(amino, AMINO_JSON);
+		let value: Value = serde_json::from_str(&amino).unwrap();
+		assert_eq!(serde_json::from_value::<MsgSwapExactAmountIn>(value["value"].clone()).unwrap(), msg());
+	}
+
+	#[test]
+	fn sorts_keys_at_every_level() {
+		let value = json!({"b": {"d": 1, "c": [{"f": 1, "e": {"h": 2, "g": 3}}]}, "a": null});
+		assert_eq!(
+			serde_json::to_string(&sort_keys(value)).unwrap(),
+			r#"{"a":null,"b":{"c":[{"e":{"g":3,"h":2},"f":1}],"d":1}}"#
+		);
+	}
+
+	#[test]
+	fn rejects_routes_it_cannot_swap() {
+		let (route, evaluation) = setup();
+		let mut other_pool = evaluation.clone();
+		other_pool.steps[1].pool_id = 2;
+		assert_eq!(
+			MsgSwapExactAmountIn::from_route(SENDER, &route, &other_pool, Decimal::percent(1)),
+			Err(FeanorError::InvalidRoute)
+		);
+		let mut short = evaluation.clone();
+		short.steps.pop();
+		assert_eq!(
+			MsgSwapExactAmountIn::from_route(SENDER, &route, &short, Decimal::percent(1)),
+			Err(FeanorError::InvalidRoute)
+		);
+		assert_eq!(
+			MsgSwapExactAmountIn::from_route(SENDER, &route, &evaluation, Decimal::ONE),
+			Err(FeanorError::SlippageExceeded)
+		);
+	}
+}
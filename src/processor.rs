@@ -0,0 +1,515 @@
+use solana_program::{
+	account_info::{next_account_info, AccountInfo},
+	bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+	entrypoint::ProgramResult,
+	instruction::Instruction,
+	msg,
+	program::{invoke, invoke_signed},
+	program_error::ProgramError,
+	pubkey::Pubkey,
+	rent::Rent,
+	system_instruction,
+	system_program,
+	sysvar::Sysvar,
+};
+
+use crate::{
+	error::FeanorError,
+	gate,
+	instruction::FeanorInstruction,
+	state::{GateConfig, Vault, VaultConfig, GATE_SEED, VAULT_SEED},
+};
+
+// dispatches decoded instructions to their handlers
+
+pub struct Processor;
+
+impl Processor {
+	pub fn process(
+		program_id: &Pubkey,
+		accounts: &[AccountInfo],
+		instruction_data: &[u8],
+		) -> ProgramResult {
+
+		let instruction = FeanorInstruction::unpack(instruction_data)?;
+
+		match instruction {
+			FeanorInstruction::InitVault { min_profit_bps } => {
+				msg!("Instruction: InitVault");
+				Self::process_init_vault(program_id, accounts, min_profit_bps)
+			}
+			FeanorInstruction::Deposit { amount } => {
+				msg!("Instruction: Deposit");
+				Self::process_deposit(program_id, accounts, amount)
+			}
+			FeanorInstruction::Withdraw { amount } => {
+				msg!("Instruction: Withdraw");
+				Self::process_withdraw(program_id, accounts, amount)
+			}
+			FeanorInstruction::RegisterRoute { route_hash } => {
+				msg!("Instruction: RegisterRoute");
+				Self::process_register_route(program_id, accounts, route_hash)
+			}
+			FeanorInstruction::ExecuteRoute { route_hash, amount_in, amount_out } => {
+				msg!("Instruction: ExecuteRoute");
+				Self::process_execute_route(program_id, accounts, route_hash, amount_in, amount_out)
+			}
+			FeanorInstruction::ConfigureGate { collection_mint, verify_collection } => {
+				msg!("Instruction: ConfigureGate");
+				Self::process_configure_gate(program_id, accounts, collection_mint, verify_collection)
+			}
+		}
+	}
+
+	fn process_init_vault(
+		program_id: &Pubkey,
+		accounts: &[AccountInfo],
+		min_profit_bps: u16,
+		) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let owner_info = next_account_info(account_info_iter)?;
+		let vault_info = next_account_info(account_info_iter)?;
+		let system_program_info = next_account_info(account_info_iter)?;
+
+		if !owner_info.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+		if *system_program_info.key != system_program::id() {
+			return Err(ProgramError::IncorrectProgramId);
+		}
+		let nft_mint = gate::check_varda_holder(program_id, owner_info, account_info_iter)?;
+
+		let (vault_address, bump) = Vault::find_address(program_id, owner_info.key);
+		if vault_address != *vault_info.key {
+			return Err(FeanorError::InvalidVaultAddress.into());
+		}
+		if !vault_info.data_is_empty() || vault_info.owner == program_id {
+			return Err(FeanorError::VaultAlreadyInitialized.into());
+		}
+
+		// create the vault PDA, funded by the owner
+
+		create_pda_account(
+			program_id,
+			owner_info,
+			vault_info,
+			system_program_info,
+			Vault::LEN,
+			&[VAULT_SEED, owner_info.key.as_ref(), &[bump]],
+		)?;
+
+		let vault = Vault {
+			is_initialized: true,
+			owner: *owner_info.key,
+			nft_mint,
+			treasury: 0,
+			profit: 0,
+			config: VaultConfig {
+				min_profit_bps,
+				route_hash: [0; 32],
+			},
+			bump,
+		};
+		vault.save(vault_info)
+	}
+
+	fn process_deposit(
+		program_id: &Pubkey,
+		accounts: &[AccountInfo],
+		amount: u64,
+		) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let owner_info = next_account_info(account_info_iter)?;
+		let vault_info = next_account_info(account_info_iter)?;
+		let system_program_info = next_account_info(account_info_iter)?;
+
+		let mut vault = Self::load_owned_vault(program_id, owner_info, vault_info)?;
+		gate::check_varda_holder(program_id, owner_info, account_info_iter)?;
+
+		invoke(
+			&system_instruction::transfer(owner_info.key, vault_info.key, amount),
+			&[owner_info.clone(), vault_info.clone(), system_program_info.clone()],
+		)?;
+
+		vault.treasury = vault.treasury.checked_add(amount).ok_or(FeanorError::MathOverflow)?;
+		vault.save(vault_info)
+	}
+
+	fn process_withdraw(
+		program_id: &Pubkey,
+		accounts: &[AccountInfo],
+		amount: u64,
+		) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let owner_info = next_account_info(account_info_iter)?;
+		let vault_info = next_account_info(account_info_iter)?;
+
+		// not gated: an owner who no longer holds the NFT can still recover
+		// its own funds, the vault being bound to it by `load_owned_vault`
+		let mut vault = Self::load_owned_vault(program_id, owner_info, vault_info)?;
+
+		// the vault must stay rent exempt after the withdrawal
+
+		let rent_floor = Rent::get()?.minimum_balance(Vault::LEN);
+		let available = vault_info.lamports().saturating_sub(rent_floor);
+		if amount > vault.treasury || amount > available {
+			return Err(FeanorError::InsufficientVaultFunds.into());
+		}
+
+		**vault_info.try_borrow_mut_lamports()? -= amount;
+		**owner_info.try_borrow_mut_lamports()? = owner_info
+			.lamports()
+			.checked_add(amount)
+			.ok_or(FeanorError::MathOverflow)?;
+
+		vault.treasury -= amount;
+		vault.save(vault_info)
+	}
+
+	fn process_register_route(
+		program_id: &Pubkey,
+		accounts: &[AccountInfo],
+		route_hash: [u8; 32],
+		) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let owner_info = next_account_info(account_info_iter)?;
+		let vault_info = next_account_info(account_info_iter)?;
+
+		let mut vault = Self::load_owned_vault(program_id, owner_info, vault_info)?;
+		gate::check_varda_holder(program_id, owner_info, account_info_iter)?;
+		vault.config.route_hash = route_hash;
+		vault.save(vault_info)
+	}
+
+	fn process_execute_route(
+		program_id: &Pubkey,
+		accounts: &[AccountInfo],
+		route_hash: [u8; 32],
+		amount_in: u64,
+		amount_out: u64,
+		) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let owner_info = next_account_info(account_info_iter)?;
+		let vault_info = next_account_info(account_info_iter)?;
+
+		let mut vault = Self::load_owned_vault(program_id, owner_info, vault_info)?;
+		gate::check_varda_holder(program_id, owner_info, account_info_iter)?;
+
+		if vault.config.route_hash == [0; 32] || vault.config.route_hash != route_hash {
+			return Err(FeanorError::InvalidRoute.into());
+		}
+
+		// the reported amounts are the owner's own record, only checked to
+		// return the loan plus the configured minimum profit
+
+		let min_profit = (amount_in as u128)
+			.checked_mul(vault.config.min_profit_bps as u128)
+			.ok_or(FeanorError::MathOverflow)?
+			/ 10_000;
+		let required = (amount_in as u128)
+			.checked_add(min_profit)
+			.ok_or(FeanorError::MathOverflow)?;
+		if (amount_out as u128) < required {
+			return Err(FeanorError::UnprofitableRoute.into());
+		}
+
+		vault.profit = vault
+			.profit
+			.checked_add(amount_out - amount_in)
+			.ok_or(FeanorError::MathOverflow)?;
+		vault.save(vault_info)
+	}
+
+	fn process_configure_gate(
+		program_id: &Pubkey,
+		accounts: &[AccountInfo],
+		collection_mint: Pubkey,
+		verify_collection: bool,
+		) -> ProgramResult {
+		let account_info_iter = &mut accounts.iter();
+		let authority_info = next_account_info(account_info_iter)?;
+		let gate_info = next_account_info(account_info_iter)?;
+		let program_data_info = next_account_info(account_info_iter)?;
+		let system_program_info = next_account_info(account_info_iter)?;
+
+		if !authority_info.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+
+		// an existing gate can only be changed by its recorded authority
+
+		if gate_info.owner == program_id {
+			let mut config = GateConfig::load(gate_info, program_id)?;
+			if config.authority != *authority_info.key {
+				return Err(FeanorError::GateAuthorityMismatch.into());
+			}
+			config.collection_mint = collection_mint;
+			config.verify_collection = verify_collection;
+			return config.save(gate_info);
+		}
+
+		// creating the gate is reserved to the program upgrade authority
+
+		let (program_data_address, _) =
+			Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+		if program_data_address != *program_data_info.key {
+			return Err(ProgramError::InvalidArgument);
+		}
+		let upgrade_authority = match bincode::deserialize(&program_data_info.data.borrow()) {
+			Ok(UpgradeableLoaderState::ProgramData { upgrade_authority_address, .. }) => {
+				upgrade_authority_address
+			}
+			_ => return Err(ProgramError::InvalidAccountData),
+		};
+		if upgrade_authority != Some(*authority_info.key) {
+			return Err(FeanorError::GateAuthorityMismatch.into());
+		}
+
+		let (gate_address, bump) = GateConfig::find_address(program_id);
+		if gate_address != *gate_info.key {
+			return Err(FeanorError::GateNotConfigured.into());
+		}
+		if *system_program_info.key != system_program::id() {
+			return Err(ProgramError::IncorrectProgramId);
+		}
+
+		create_pda_account(
+			program_id,
+			authority_info,
+			gate_info,
+			system_program_info,
+			GateConfig::LEN,
+			&[GATE_SEED, &[bump]],
+		)?;
+
+		let config = GateConfig {
+			is_initialized: true,
+			authority: *authority_info.key,
+			collection_mint,
+			verify_collection,
+			bump,
+		};
+		config.save(gate_info)
+	}
+
+	// load the vault behind `vault_info` and check `owner_info` signed for it
+
+	fn load_owned_vault(
+		program_id: &Pubkey,
+		owner_info: &AccountInfo,
+		vault_info: &AccountInfo,
+		) -> Result<Vault, ProgramError> {
+		if !owner_info.is_signer {
+			return Err(ProgramError::MissingRequiredSignature);
+		}
+		let vault = Vault::load(vault_info, program_id)?;
+		vault.check_address(vault_info, program_id)?;
+		if vault.owner != *owner_info.key {
+			return Err(FeanorError::VaultOwnerMismatch.into());
+		}
+		Ok(vault)
+	}
+}
+
+// create the PDA behind `account_info`, signed for with `seeds`, as a rent
+// exempt account of `space` bytes owned by the program
+//
+// `create_account` fails once the address holds lamports, which anyone can
+// send to a PDA ahead of its creation; the missing rent is transferred instead
+// and the account allocated and assigned separately
+
+fn create_pda_account<'a>(
+	program_id: &Pubkey,
+	payer_info: &AccountInfo<'a>,
+	account_info: &AccountInfo<'a>,
+	system_program_info: &AccountInfo<'a>,
+	space: usize,
+	seeds: &[&[u8]],
+	) -> ProgramResult {
+	let required = Rent::get()?.minimum_balance(space);
+	let instructions = create_pda_instructions(
+		payer_info.key,
+		account_info.key,
+		account_info.lamports(),
+		required,
+		space,
+		program_id,
+	);
+	for instruction in instructions {
+		invoke_signed(
+			&instruction,
+			&[payer_info.clone(), account_info.clone(), system_program_info.clone()],
+			&[seeds],
+		)?;
+	}
+	Ok(())
+}
+
+/// system instructions turning the empty system account `account`, which
+/// already holds `lamports`, into a `required` lamports account of `space`
+/// bytes owned by `owner`
+pub fn create_pda_instructions(
+	payer: &Pubkey,
+	account: &Pubkey,
+	lamports: u64,
+	required: u64,
+	space: usize,
+	owner: &Pubkey,
+	) -> Vec<Instruction> {
+	let mut instructions = Vec::with_capacity(3);
+	if lamports < required {
+		instructions.push(system_instruction::transfer(payer, account, required - lamports));
+	}
+	instructions.push(system_instruction::allocate(account, space as u64));
+	instructions.push(system_instruction::assign(account, owner));
+	instructions
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Once;
+
+	use solana_program::{entrypoint::SUCCESS, program_stubs, system_instruction::SystemInstruction};
+
+	use super::*;
+
+	// answers `Rent::get` off-chain, the other syscalls keeping their defaults
+
+	struct RentStub;
+
+	impl program_stubs::SyscallStubs for RentStub {
+		fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+			unsafe { *(var_addr as *mut Rent) = Rent::default() };
+			SUCCESS
+		}
+	}
+
+	fn stub_rent() {
+		static STUBS: Once = Once::new();
+		STUBS.call_once(|| {
+			program_stubs::set_syscall_stubs(Box::new(RentStub));
+		});
+	}
+
+	fn vault_data(owner: Pubkey, bump: u8, treasury: u64) -> Vec<u8> {
+		let vault = Vault {
+			is_initialized: true,
+			owner,
+			treasury,
+			bump,
+			..Vault::default()
+		};
+		borsh::to_vec(&vault).unwrap()
+	}
+
+	fn decode(instruction: &Instruction) -> SystemInstruction {
+		assert_eq!(instruction.program_id, system_program::id());
+		bincode::deserialize(&instruction.data).unwrap()
+	}
+
+	#[test]
+	fn creates_an_unfunded_pda_with_the_full_rent() {
+		let (payer, vault, program_id) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+		let instructions = create_pda_instructions(&payer, &vault, 0, 1_000, Vault::LEN, &program_id);
+		let decoded: Vec<_> = instructions.iter().map(decode).collect();
+		assert_eq!(
+			decoded,
+			vec![
+				SystemInstruction::Transfer { lamports: 1_000 },
+				SystemInstruction::Allocate { space: Vault::LEN as u64 },
+				SystemInstruction::Assign { owner: program_id },
+			]
+		);
+		assert_eq!(instructions[0].accounts[0].pubkey, payer);
+		assert!(instructions[0].accounts[0].is_signer);
+		// allocate and assign need the signature of the PDA itself
+		for instruction in &instructions[1..] {
+			assert_eq!(instruction.accounts[0].pubkey, vault);
+			assert!(instruction.accounts[0].is_signer);
+		}
+	}
+
+	#[test]
+	fn tops_up_a_pda_funded_ahead_of_its_creation() {
+		let (payer, vault, program_id) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+		let instructions = create_pda_instructions(&payer, &vault, 400, 1_000, Vault::LEN, &program_id);
+		assert_eq!(decode(&instructions[0]), SystemInstruction::Transfer { lamports: 600 });
+		assert_eq!(instructions.len(), 3);
+
+		// nothing to transfer once the rent is already there
+		for lamports in [1_000, 5_000] {
+			let instructions = create_pda_instructions(&payer, &vault, lamports, 1_000, Vault::LEN, &program_id);
+			let decoded: Vec<_> = instructions.iter().map(decode).collect();
+			assert_eq!(
+				decoded,
+				vec![
+					SystemInstruction::Allocate { space: Vault::LEN as u64 },
+					SystemInstruction::Assign { owner: program_id },
+				]
+			);
+		}
+	}
+
+	#[test]
+	fn withdraws_without_the_gate_accounts() {
+		stub_rent();
+		let program_id = Pubkey::new_unique();
+		let owner = Pubkey::new_unique();
+		let (vault, bump) = Vault::find_address(&program_id, &owner);
+		let rent_floor = Rent::default().minimum_balance(Vault::LEN);
+		let (mut owner_lamports, mut owner_data) = (10, vec![]);
+		let (mut vault_lamports, mut data) = (rent_floor + 5_000, vault_data(owner, bump, 5_000));
+		let system = system_program::id();
+		let accounts = [
+			AccountInfo::new(&owner, true, true, &mut owner_lamports, &mut owner_data, &system, false, 0),
+			AccountInfo::new(&vault, false, true, &mut vault_lamports, &mut data, &program_id, false, 0),
+		];
+
+		let data = FeanorInstruction::Withdraw { amount: 3_000 }.pack();
+		Processor::process(&program_id, &accounts, &data).unwrap();
+		assert_eq!(accounts[0].lamports(), 3_010);
+		assert_eq!(accounts[1].lamports(), rent_floor + 2_000);
+		assert_eq!(Vault::load(&accounts[1], &program_id).unwrap().treasury, 2_000);
+
+		// the rent exempt floor stays out of reach
+		let data = FeanorInstruction::Withdraw { amount: 2_001 }.pack();
+		assert_eq!(
+			Processor::process(&program_id, &accounts, &data),
+			Err(FeanorError::InsufficientVaultFunds.into())
+		);
+	}
+
+	#[test]
+	fn other_owner_instructions_require_the_gate_accounts() {
+		let program_id = Pubkey::new_unique();
+		let owner = Pubkey::new_unique();
+		let (vault, bump) = Vault::find_address(&program_id, &owner);
+		let (mut owner_lamports, mut owner_data) = (10, vec![]);
+		let (mut vault_lamports, mut data) = (10, vault_data(owner, bump, 0));
+		let (mut system_lamports, mut system_data) = (1, vec![]);
+		let system = system_program::id();
+		let accounts = [
+			AccountInfo::new(&owner, true, true, &mut owner_lamports, &mut owner_data, &system, false, 0),
+			AccountInfo::new(&vault, false, true, &mut vault_lamports, &mut data, &program_id, false, 0),
+			AccountInfo::new(&system, false, false, &mut system_lamports, &mut system_data, &system, true, 0),
+		];
+
+		let instructions = [
+			FeanorInstruction::Deposit { amount: 1 },
+			FeanorInstruction::RegisterRoute { route_hash: [1; 32] },
+			FeanorInstruction::ExecuteRoute {
+				route_hash: [1; 32],
+				amount_in: 1,
+				amount_out: 2,
+			},
+		];
+		for instruction in instructions {
+			assert_eq!(
+				Processor::process(&program_id, &accounts, &instruction.pack()),
+				Err(ProgramError::NotEnoughAccountKeys),
+				"{:?}",
+				instruction
+			);
+		}
+	}
+}
@@ -0,0 +1,113 @@
+// minimal protobuf wire encoding for the Cosmos messages the bot submits
+//
+// follows proto3 the way the generated Go and ts-proto encoders do: fields are
+// written in field number order and scalar fields holding their default value
+// are left out, so the bytes match cosmjs for the same message
+
+/// protobuf wire types
+const WIRE_VARINT: u64 = 0;
+const WIRE_LEN: u64 = 2;
+
+/// a message that can be written in protobuf wire format
+pub trait Message {
+	fn encode_raw(&self, buf: &mut Vec<u8>);
+
+	fn encode_to_vec(&self) -> Vec<u8> {
+		let mut buf = Vec::new();
+		self.encode_raw(&mut buf);
+		buf
+	}
+}
+
+/// `google.protobuf.Any`, the envelope of every Cosmos SDK message
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Any {
+	pub type_url: String,
+	pub value: Vec<u8>,
+}
+
+impl Any {
+	pub fn pack<M: Message>(type_url: &str, msg: &M) -> Self {
+		Any {
+			type_url: type_url.to_string(),
+			value: msg.encode_to_vec(),
+		}
+	}
+}
+
+impl Message for Any {
+	fn encode_raw(&self, buf: &mut Vec<u8>) {
+		string(1, &self.type_url, buf);
+		bytes(2, &self.value, buf);
+	}
+}
+
+pub fn varint(mut value: u64, buf: &mut Vec<u8>) {
+	while value >= 0x80 {
+		buf.push((value as u8) | 0x80);
+		value >>= 7;
+	}
+	buf.push(value as u8);
+}
+
+fn key(field: u32, wire_type: u64, buf: &mut Vec<u8>) {
+	varint((u64::from(field) << 3) | wire_type, buf);
+}
+
+pub fn uint64(field: u32, value: u64, buf: &mut Vec<u8>) {
+	if value != 0 {
+		key(field, WIRE_VARINT, buf);
+		varint(value, buf);
+	}
+}
+
+/// enums are encoded as their varint number
+pub fn int32(field: u32, value: i32, buf: &mut Vec<u8>) {
+	if value != 0 {
+		key(field, WIRE_VARINT, buf);
+		// negative values are sign extended to 10 bytes
+		varint(i64::from(value) as u64, buf);
+	}
+}
+
+pub fn bool(field: u32, value: bool, buf: &mut Vec<u8>) {
+	if value {
+		key(field, WIRE_VARINT, buf);
+		varint(1, buf);
+	}
+}
+
+pub fn bytes(field: u32, value: &[u8], buf: &mut Vec<u8>) {
+	if !value.is_empty() {
+		key(field, WIRE_LEN, buf);
+		varint(value.len() as u64, buf);
+		buf.extend_from_slice(value);
+	}
+}
+
+/// every element is written, empty ones included
+pub fn repeated_bytes(field: u32, values: &[Vec<u8>], buf: &mut Vec<u8>) {
+	for value in values {
+		key(field, WIRE_LEN, buf);
+		varint(value.len() as u64, buf);
+		buf.extend_from_slice(value);
+	}
+}
+
+pub fn string(field: u32, value: &str, buf: &mut Vec<u8>) {
+	bytes(field, value.as_bytes(), buf);
+}
+
+/// embedded message, always written when present even if it encodes to nothing
+pub fn message<M: Message>(field: u32, msg: &M, buf: &mut Vec<u8>) {
+	let value = msg.encode_to_vec();
+	key(field, WIRE_LEN, buf);
+	varint(value.len() as u64, buf);
+	buf.extend_from_slice(&value);
+}
+
+pub fn repeated<M: Message>(field: u32, msgs: &[M], buf: &mut Vec<u8>) {
+	for msg in msgs {
+		message(field, msg, buf);
+	}
+}
@@ -0,0 +1,404 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	denom::{ibc_denom_hash_of_path, Addr, Denom},
+	error::FeanorError,
+	route::RouteToken,
+};
+
+// asset registry loaded from a chain-registry `assetlist.json`
+//
+// replaces the `DEFAULT_DECIMALS = 6` fallback of `makeRouteTokenDetails`:
+// an unknown denom is an error instead of a silently mis-scaled amount. the
+// chain-registry lists hundreds of assets, a malformed one is set aside with
+// its reason rather than failing the whole list
+
+/// prefix of the base of CW20 assets, `cw20:<contract>`
+const CW20_PREFIX: &str = "cw20:";
+
+/// an asset of the registry, keyed by its base denom
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct RegisteredAsset {
+	pub denom: Denom,
+	pub symbol: String,
+	/// exponent of the display unit, e.g. 6 for OSMO and 18 for most EVM assets
+	pub decimals: u8,
+	pub logo: Option<String>,
+	/// where an `ibc/` voucher comes from
+	pub trace: Option<IbcTrace>,
+}
+
+/// origin of an IBC voucher, from the last `ibc` or `ibc-cw20` trace of the asset list
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct IbcTrace {
+	pub counterparty_chain: String,
+	pub base_denom: String,
+	/// channel on the counterparty chain
+	pub counterparty_channel: String,
+	/// channel on this chain
+	pub channel: String,
+	/// full transfer path, e.g. `transfer/channel-0/uatom`
+	pub path: String,
+}
+
+#[derive(Default)]
+pub struct AssetRegistry {
+	chain_name: String,
+	assets: HashMap<String, RegisteredAsset>,
+	rejected: Vec<(String, FeanorError)>,
+}
+
+impl AssetRegistry {
+	/// parse a chain-registry `assetlist.json` document, the document itself
+	/// being an error when it is not an asset list
+	pub fn from_json(json: &str) -> Result<Self, FeanorError> {
+		let list: AssetList = serde_json::from_str(json).map_err(|_| FeanorError::InvalidAssetList)?;
+		let mut registry = AssetRegistry {
+			chain_name: list.chain_name,
+			assets: HashMap::with_capacity(list.assets.len()),
+			rejected: Vec::new(),
+		};
+		for (index, value) in list.assets.into_iter().enumerate() {
+			// the base when there is one, the position in the list otherwise
+			let base = match value.get("base").and_then(|base| base.as_str()) {
+				Some(base) => base.to_string(),
+				None => format!("#{}", index),
+			};
+			let asset = serde_json::from_value::<AssetEntry>(value)
+				.map_err(|_| FeanorError::InvalidAssetList)
+				.and_then(AssetEntry::into_asset)
+				.and_then(|asset| registry.insert(asset));
+			if let Err(e) = asset {
+				registry.rejected.push((base, e));
+			}
+		}
+		Ok(registry)
+	}
+
+	/// add an asset, two entries for the same denom being an error
+	pub fn insert(&mut self, asset: RegisteredAsset) -> Result<(), FeanorError> {
+		let denom = asset.denom.to_string();
+		if self.assets.contains_key(&denom) {
+			return Err(FeanorError::InvalidAssetList);
+		}
+		self.assets.insert(denom, asset);
+		Ok(())
+	}
+
+	pub fn chain_name(&self) -> &str {
+		&self.chain_name
+	}
+
+	/// base of the entries left out of the registry and the reason
+	pub fn rejected(&self) -> &[(String, FeanorError)] {
+		&self.rejected
+	}
+
+	pub fn get(&self, denom: &str) -> Result<&RegisteredAsset, FeanorError> {
+		self.assets.get(denom).ok_or(FeanorError::UnknownAsset)
+	}
+
+	pub fn decimals(&self, denom: &str) -> Result<u8, FeanorError> {
+		self.get(denom).map(|asset| asset.decimals)
+	}
+
+	/// first asset with `symbol`, symbols are not unique across bridges
+	pub fn find_symbol(&self, symbol: &str) -> Result<&RegisteredAsset, FeanorError> {
+		let mut matches: Vec<_> = self
+			.assets
+			.values()
+			.filter(|asset| asset.symbol.eq_ignore_ascii_case(symbol))
+			.collect();
+		// deterministic whatever the map order
+		matches.sort_by_key(|asset| asset.denom.to_string());
+		matches.into_iter().next().ok_or(FeanorError::UnknownAsset)
+	}
+
+	/// route token of `denom`, with the registry logo when there is one
+	pub fn route_token(&self, denom: &str) -> Result<RouteToken, FeanorError> {
+		let asset = self.get(denom)?;
+		let mut token = RouteToken::new(&asset.symbol, denom, asset.decimals);
+		if let Some(logo) = &asset.logo {
+			token.logo = logo.clone();
+		}
+		Ok(token)
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = &RegisteredAsset> {
+		self.assets.values()
+	}
+
+	pub fn len(&self) -> usize {
+		self.assets.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.assets.is_empty()
+	}
+}
+
+// chain-registry schema, only the fields used here
+
+#[derive(Deserialize)]
+struct AssetList {
+	chain_name: String,
+	/// parsed one by one, see `AssetRegistry::from_json`
+	assets: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct AssetEntry {
+	base: String,
+	display: String,
+	symbol: String,
+	denom_units: Vec<DenomUnit>,
+	#[serde(default, rename = "logo_URIs")]
+	logo_uris: Option<LogoUris>,
+	#[serde(default)]
+	traces: Vec<Trace>,
+}
+
+#[derive(Deserialize)]
+struct DenomUnit {
+	denom: String,
+	exponent: u8,
+}
+
+#[derive(Deserialize)]
+struct LogoUris {
+	png: Option<String>,
+	svg: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Trace {
+	#[serde(rename = "type")]
+	type_: String,
+	counterparty: TraceCounterparty,
+	#[serde(default)]
+	chain: Option<TraceChain>,
+}
+
+#[derive(Deserialize)]
+struct TraceCounterparty {
+	chain_name: String,
+	base_denom: String,
+	#[serde(default)]
+	channel_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TraceChain {
+	channel_id: String,
+	path: String,
+}
+
+impl AssetEntry {
+	fn into_asset(self) -> Result<RegisteredAsset, FeanorError> {
+		let denom = match self.base.strip_prefix(CW20_PREFIX) {
+			Some(contract) => Denom::Cw20 {
+				addr: contract.parse::<Addr>().map_err(|_| FeanorError::InvalidAssetList)?,
+			},
+			None => self.base.parse().map_err(|_| FeanorError::InvalidAssetList)?,
+		};
+
+		// the display unit carries the decimals, it has to be listed
+		let decimals = self
+			.denom_units
+			.iter()
+			.find(|unit| unit.denom == self.display)
+			.map(|unit| unit.exponent)
+			.ok_or(FeanorError::InvalidAssetList)?;
+
+		let trace = match self.traces.into_iter().rev().find(|trace| trace.type_.starts_with("ibc")) {
+			Some(Trace { counterparty, chain: Some(chain), .. }) => Some(IbcTrace {
+				counterparty_chain: counterparty.chain_name,
+				base_denom: counterparty.base_denom,
+				counterparty_channel: counterparty.channel_id.unwrap_or_default(),
+				channel: chain.channel_id,
+				path: chain.path,
+			}),
+			Some(_) => return Err(FeanorError::InvalidAssetList),
+			None => None,
+		};
+
+		// an `ibc/` denom must be the hash of its own trace
+		match (&denom, &trace) {
+			(Denom::Ibc { hash }, Some(trace)) if *hash != ibc_denom_hash_of_path(&trace.path) => {
+				return Err(FeanorError::InvalidAssetList);
+			}
+			(Denom::Ibc { .. }, None) => return Err(FeanorError::InvalidAssetList),
+			_ => {}
+		}
+
+		let logo = self.logo_uris.and_then(|uris| uris.png.or(uris.svg));
+		Ok(RegisteredAsset {
+			denom,
+			symbol: self.symbol,
+			decimals,
+			logo,
+			trace,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const CONTRACT: &str = "osmo1qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurstc4vrk";
+	const ATOM: &str = "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2";
+	const JUNO_CW20: &str = "ibc/6C9CF0DFF34A682FB8C6E05398C959040D01F0B456F8C18204F445F14F876D07";
+
+	// trimmed entries of the osmosis `assetlist.json`, the last five broken on purpose
+	const ASSET_LIST: &str = r#"{
+		"chain_name": "osmosis",
+		"assets": [
+			{
+				"base": "uosmo",
+				"display": "osmo",
+				"symbol": "OSMO",
+				"denom_units": [{"denom": "uosmo", "exponent": 0}, {"denom": "osmo", "exponent": 6}],
+				"logo_URIs": {"png": "https://example.org/osmo.png", "svg": "https://example.org/osmo.svg"}
+			},
+			{
+				"base": "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2",
+				"display": "atom",
+				"symbol": "ATOM",
+				"denom_units": [{"denom": "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2", "exponent": 0}, {"denom": "atom", "exponent": 6}],
+				"logo_URIs": {"svg": "https://example.org/atom.svg"},
+				"traces": [{
+					"type": "ibc",
+					"counterparty": {"chain_name": "cosmoshub", "base_denom": "uatom", "channel_id": "channel-141"},
+					"chain": {"channel_id": "channel-0", "path": "transfer/channel-0/uatom"}
+				}]
+			},
+			{
+				"base": "ibc/6C9CF0DFF34A682FB8C6E05398C959040D01F0B456F8C18204F445F14F876D07",
+				"display": "tok",
+				"symbol": "TOK.juno",
+				"denom_units": [{"denom": "tok", "exponent": 6}],
+				"traces": [{
+					"type": "ibc-cw20",
+					"counterparty": {"chain_name": "juno", "base_denom": "cw20:juno1qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurs", "port": "wasm.juno1", "channel_id": "channel-47"},
+					"chain": {"port": "transfer", "channel_id": "channel-169", "path": "transfer/channel-169/cw20:juno1qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurs"}
+				}]
+			},
+			{
+				"base": "cw20:osmo1qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurstc4vrk",
+				"display": "cwt",
+				"symbol": "CWT",
+				"denom_units": [{"denom": "cw20:osmo1qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurstc4vrk", "exponent": 0}, {"denom": "cwt", "exponent": 8}]
+			},
+			{
+				"base": "ibc/0000000000000000000000000000000000000000000000000000000000000000",
+				"display": "fake",
+				"symbol": "FAKE",
+				"denom_units": [{"denom": "fake", "exponent": 6}],
+				"traces": [{
+					"type": "ibc",
+					"counterparty": {"chain_name": "cosmoshub", "base_denom": "uatom"},
+					"chain": {"channel_id": "channel-0", "path": "transfer/channel-0/uatom"}
+				}]
+			},
+			{
+				"base": "cw20:osmo1qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurstc4vrx",
+				"display": "bad",
+				"symbol": "BAD",
+				"denom_units": [{"denom": "bad", "exponent": 6}]
+			},
+			{
+				"base": "uion",
+				"display": "ion",
+				"symbol": "ION"
+			},
+			{
+				"display": "nobase",
+				"symbol": "NOBASE",
+				"denom_units": [{"denom": "nobase", "exponent": 6}]
+			},
+			{
+				"base": "uosmo",
+				"display": "uosmo",
+				"symbol": "OSMO",
+				"denom_units": [{"denom": "uosmo", "exponent": 0}]
+			}
+		]
+	}"#;
+
+	#[test]
+	fn parses_native_ibc_and_cw20_assets() {
+		let registry = AssetRegistry::from_json(ASSET_LIST).unwrap();
+		assert_eq!(registry.chain_name(), "osmosis");
+		assert_eq!(registry.len(), 4);
+
+		let osmo = registry.get("uosmo").unwrap();
+		assert_eq!(osmo.denom, Denom::Native("uosmo".to_string()));
+		assert_eq!(osmo.decimals, 6);
+		assert_eq!(osmo.logo.as_deref(), Some("https://example.org/osmo.png"));
+		assert_eq!(osmo.trace, None);
+
+		let atom = registry.get(ATOM).unwrap();
+		assert_eq!(atom.denom, Denom::ibc("transfer", "channel-0", "uatom"));
+		assert_eq!(atom.logo.as_deref(), Some("https://example.org/atom.svg"));
+		assert_eq!(
+			atom.trace,
+			Some(IbcTrace {
+				counterparty_chain: "cosmoshub".to_string(),
+				base_denom: "uatom".to_string(),
+				counterparty_channel: "channel-141".to_string(),
+				channel: "channel-0".to_string(),
+				path: "transfer/channel-0/uatom".to_string(),
+			})
+		);
+
+		// a CW20 of another chain is an ibc voucher here
+		let tok = registry.get(JUNO_CW20).unwrap();
+		assert_eq!(tok.trace.as_ref().unwrap().counterparty_chain, "juno");
+
+		let cwt = registry.get(CONTRACT).unwrap();
+		assert_eq!(
+			cwt.denom,
+			Denom::Cw20 {
+				addr: CONTRACT.parse().unwrap()
+			}
+		);
+		assert_eq!(registry.decimals(CONTRACT), Ok(8));
+		assert_eq!(registry.find_symbol("cwt").unwrap().denom, cwt.denom);
+		assert_eq!(registry.route_token("uosmo").unwrap().decimals, 6);
+	}
+
+	#[test]
+	fn sets_broken_entries_aside() {
+		let registry = AssetRegistry::from_json(ASSET_LIST).unwrap();
+		let rejected: Vec<&str> = registry.rejected().iter().map(|(base, _)| base.as_str()).collect();
+		assert_eq!(
+			rejected,
+			[
+				"ibc/0000000000000000000000000000000000000000000000000000000000000000",
+				"cw20:osmo1qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurstc4vrx",
+				"uion",
+				"#7",
+				"uosmo",
+			]
+		);
+		assert!(registry
+			.rejected()
+			.iter()
+			.all(|(_, e)| *e == FeanorError::InvalidAssetList));
+		// the duplicate did not replace the first entry
+		assert_eq!(registry.decimals("uosmo"), Ok(6));
+		assert_eq!(registry.get("uion"), Err(FeanorError::UnknownAsset));
+	}
+
+	#[test]
+	fn rejects_documents_that_are_not_asset_lists() {
+		assert_eq!(AssetRegistry::from_json("[]").err(), Some(FeanorError::InvalidAssetList));
+		assert_eq!(
+			AssetRegistry::from_json(r#"{"chain_name": "osmosis"}"#).err(),
+			Some(FeanorError::InvalidAssetList)
+		);
+	}
+}
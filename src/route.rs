@@ -0,0 +1,359 @@
+use serde::{Deserialize, Serialize};
+use solana_program::hash::hashv;
+
+use crate::{
+	amm::PoolSet,
+	error::FeanorError,
+	math::Decimal,
+};
+
+// typed form of the route JSON built by `createRouteObject` in wrapJson.js
+//
+// field names and the "N/A" / "Error" placeholders are kept as-is so the files
+// produced by the dashboard round-trip unchanged
+
+/// a token as it appears at either end of a route step
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct RouteToken {
+	pub symbol: String,
+	pub logo: String,
+	pub denom: String,
+	pub decimals: u8,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub error: Option<String>,
+}
+
+impl RouteToken {
+	pub fn new(symbol: &str, denom: &str, decimals: u8) -> Self {
+		let symbol = symbol.to_uppercase();
+		RouteToken {
+			logo: format!("/images/{}.png", symbol.to_lowercase()),
+			symbol,
+			denom: denom.to_string(),
+			decimals,
+			error: None,
+		}
+	}
+}
+
+/// one swap of a route, from `from_token` to `to_token` through `pool_id`
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RouteStep {
+	/// numeric pool id, or "N/A" when the pool could not be resolved
+	pub pool_id: String,
+	/// swap fee as a decimal string, or "N/A" / "Error" when it could not be fetched
+	pub swap_fee: String,
+	pub from_token: RouteToken,
+	pub to_token: RouteToken,
+	pub pool_provider: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub error: Option<String>,
+}
+
+impl RouteStep {
+	pub fn new(pool_id: u64, swap_fee: Decimal, from_token: RouteToken, to_token: RouteToken, pool_provider: &str) -> Self {
+		RouteStep {
+			pool_id: pool_id.to_string(),
+			swap_fee: swap_fee.to_string(),
+			from_token,
+			to_token,
+			pool_provider: pool_provider.to_string(),
+			error: None,
+		}
+	}
+
+	/// the pool id as a number, if it was resolved
+	pub fn pool_id(&self) -> Option<u64> {
+		self.pool_id.parse().ok()
+	}
+}
+
+/// simulated outcome of one route step
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct StepEvaluation {
+	pub pool_id: u64,
+	pub denom_in: String,
+	pub denom_out: String,
+	pub amount_in: u128,
+	pub amount_out: u128,
+	/// swap fee paid, in `denom_in`
+	pub fee_paid: u128,
+	pub price_impact: Decimal,
+}
+
+/// simulated outcome of a whole route
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct RouteEvaluation {
+	pub amount_in: u128,
+	pub amount_out: u128,
+	pub steps: Vec<StepEvaluation>,
+	/// `amount_out - amount_in` in the loan denom, negative when the route loses
+	pub net_profit: i128,
+}
+
+impl RouteEvaluation {
+	pub fn is_profitable(&self) -> bool {
+		self.net_profit > 0
+	}
+}
+
+/// a full arbitrage path from `from` back to `to`
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct Route {
+	pub from: RouteToken,
+	pub to: RouteToken,
+	pub steps: Vec<RouteStep>,
+}
+
+impl Route {
+	/// parse a route JSON document and validate it
+	pub fn from_json(json: &str) -> Result<Self, FeanorError> {
+		let route: Route = serde_json::from_str(json).map_err(|_| FeanorError::InvalidRoute)?;
+		route.validate()?;
+		Ok(route)
+	}
+
+	/// serialize the route back to the dashboard JSON format
+	pub fn to_json(&self) -> String {
+		serde_json::to_string(self).expect("route serialization cannot fail")
+	}
+
+	/// check that the route is not empty and every step feeds the next one
+	pub fn validate(&self) -> Result<(), FeanorError> {
+		let (first, last) = match (self.steps.first(), self.steps.last()) {
+			(Some(first), Some(last)) => (first, last),
+			_ => return Err(FeanorError::InvalidRoute),
+		};
+		if first.from_token.denom != self.from.denom || last.to_token.denom != self.to.denom {
+			return Err(FeanorError::InvalidRoute);
+		}
+		for pair in self.steps.windows(2) {
+			if pair[0].to_token.denom != pair[1].from_token.denom {
+				return Err(FeanorError::InvalidRoute);
+			}
+		}
+		Ok(())
+	}
+
+	/// simulate the route for `amount_in` of the loan denom, whatever its number of hops
+	///
+	/// the route must start and end on the same denom so the profit can be
+	/// expressed in it
+	pub fn evaluate(&self, amount_in: u128, pools: &PoolSet) -> Result<RouteEvaluation, FeanorError> {
+		self.validate()?;
+		if self.from.denom != self.to.denom {
+			return Err(FeanorError::InvalidRoute);
+		}
+
+		let mut amount = amount_in;
+		let mut steps = Vec::with_capacity(self.steps.len());
+		for step in &self.steps {
+			let pool_id = step.pool_id().ok_or(FeanorError::InvalidRoute)?;
+			let result = pools.get(pool_id)?.swap_exact_in(
+				&step.from_token.denom,
+				&step.to_token.denom,
+				amount,
+			)?;
+			steps.push(StepEvaluation {
+				pool_id,
+				denom_in: step.from_token.denom.clone(),
+				denom_out: step.to_token.denom.clone(),
+				amount_in: result.amount_in,
+				amount_out: result.amount_out,
+				fee_paid: result.fee_paid,
+				price_impact: result.price_impact,
+			});
+			amount = result.amount_out;
+		}
+
+		let net_profit = i128::try_from(amount)
+			.ok()
+			.zip(i128::try_from(amount_in).ok())
+			.map(|(out, loan)| out - loan)
+			.ok_or(FeanorError::MathOverflow)?;
+		Ok(RouteEvaluation {
+			amount_in,
+			amount_out: amount,
+			steps,
+			net_profit,
+		})
+	}
+
+	/// hash registered on-chain with `RegisterRoute`, over the pools and denoms of the path
+	pub fn hash(&self) -> [u8; 32] {
+		// fields are NUL separated so adjacent ids and denoms cannot run together
+		let mut parts: Vec<&[u8]> = vec![self.from.denom.as_bytes()];
+		for step in &self.steps {
+			parts.extend([b"\0".as_slice(), step.pool_id.as_bytes()]);
+			parts.extend([b"\0".as_slice(), step.to_token.denom.as_bytes()]);
+		}
+		hashv(&parts).to_bytes()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::amm::{ConstantProduct, PoolAsset};
+
+	fn osmo() -> RouteToken {
+		RouteToken::new("osmo", "uosmo", 6)
+	}
+
+	fn atom() -> RouteToken {
+		RouteToken::new("atom", "uatom", 6)
+	}
+
+	fn usdc() -> RouteToken {
+		RouteToken::new("usdc", "uusdc", 6)
+	}
+
+	fn step(pool_id: u64, from: RouteToken, to: RouteToken) -> RouteStep {
+		RouteStep::new(pool_id, Decimal::ZERO, from, to, "osmosis")
+	}
+
+	// uosmo -> uatom -> uusdc -> uosmo
+	fn triangle() -> Route {
+		Route {
+			from: osmo(),
+			to: osmo(),
+			steps: vec![step(1, osmo(), atom()), step(2, atom(), usdc()), step(3, usdc(), osmo())],
+		}
+	}
+
+	#[test]
+	fn reads_the_dashboard_json() {
+		let json = r#"{
+			"from":{"symbol":"OSMO","logo":"/images/osmo.png","denom":"uosmo","decimals":6},
+			"to":{"symbol":"OSMO","logo":"/images/osmo.png","denom":"uosmo","decimals":6},
+			"steps":[
+				{"poolId":"1","swapFee":"0.002","poolProvider":"osmosis",
+				"fromToken":{"symbol":"OSMO","logo":"/images/osmo.png","denom":"uosmo","decimals":6},
+				"toToken":{"symbol":"ATOM","logo":"/images/atom.png","denom":"uatom","decimals":6}},
+				{"poolId":"N/A","swapFee":"Error","poolProvider":"osmosis","error":"pool not found",
+				"fromToken":{"symbol":"ATOM","logo":"/images/atom.png","denom":"uatom","decimals":6},
+				"toToken":{"symbol":"OSMO","logo":"/images/osmo.png","denom":"uosmo","decimals":6}}
+			]
+		}"#;
+		let route = Route::from_json(json).unwrap();
+		assert_eq!(route.from, osmo());
+		assert_eq!(route.steps[0].pool_id(), Some(1));
+		assert_eq!(route.steps[0].swap_fee, "0.002");
+		assert_eq!(route.steps[1].pool_id(), None);
+		assert_eq!(route.steps[1].error.as_deref(), Some("pool not found"));
+		assert_eq!(Route::from_json(&route.to_json()).unwrap(), route);
+		// unset errors are left out rather than written as null
+		assert!(!triangle().to_json().contains("error"));
+	}
+
+	#[test]
+	fn rejects_routes_whose_steps_do_not_chain() {
+		assert_eq!(triangle().validate(), Ok(()));
+
+		let mut empty = triangle();
+		empty.steps.clear();
+		assert_eq!(empty.validate(), Err(FeanorError::InvalidRoute));
+
+		let mut wrong_start = triangle();
+		wrong_start.from = atom();
+		assert_eq!(wrong_start.validate(), Err(FeanorError::InvalidRoute));
+
+		let mut wrong_end = triangle();
+		wrong_end.to = usdc();
+		assert_eq!(wrong_end.validate(), Err(FeanorError::InvalidRoute));
+
+		let mut broken = triangle();
+		broken.steps[1] = step(2, usdc(), atom());
+		assert_eq!(broken.validate(), Err(FeanorError::InvalidRoute));
+
+		assert_eq!(Route::from_json(&broken.to_json()), Err(FeanorError::InvalidRoute));
+		assert_eq!(Route::from_json("{\"from\":{}}"), Err(FeanorError::InvalidRoute));
+	}
+
+	#[test]
+	fn hashes_the_path_only() {
+		let route = triangle();
+		let mut relabelled = triangle();
+		relabelled.steps[0].swap_fee = "0.003".into();
+		relabelled.steps[0].pool_provider = "numia".into();
+		relabelled.from.symbol = "WOSMO".into();
+		assert_eq!(relabelled.hash(), route.hash());
+
+		let mut other_pool = triangle();
+		other_pool.steps[1].pool_id = "22".into();
+		assert_ne!(other_pool.hash(), route.hash());
+
+		// "1" + "2x" and "12" + "x" must not collide
+		let joined = |first: &str, second: &str| {
+			let mut route = triangle();
+			route.steps[0].pool_id = first.into();
+			route.steps[0].to_token.denom = second.into();
+			route.hash()
+		};
+		assert_ne!(joined("1", "2uatom"), joined("12", "uatom"));
+	}
+
+	fn pools() -> PoolSet {
+		let asset = |denom: &str, reserve| PoolAsset { denom: denom.into(), reserve, weight: 1 };
+		let fee = "0.002".parse().unwrap();
+		let mut pools = PoolSet::new();
+		pools.insert(1, ConstantProduct::new([asset("uosmo", 1_000_000), asset("uatom", 500_000)], fee).unwrap());
+		pools.insert(2, ConstantProduct::new([asset("uatom", 500_000), asset("uusdc", 2_000_000)], Decimal::ZERO).unwrap());
+		pools.insert(3, ConstantProduct::new([asset("uusdc", 2_000_000), asset("uosmo", 3_000_000)], Decimal::ZERO).unwrap());
+		pools
+	}
+
+	#[test]
+	fn evaluates_every_hop_in_order() {
+		let evaluation = triangle().evaluate(10_000, &pools()).unwrap();
+		// x * y = k by hand, each output rounded down
+		let hops: Vec<_> = evaluation
+			.steps
+			.iter()
+			.map(|step| (step.pool_id, step.denom_in.as_str(), step.amount_in, step.amount_out, step.fee_paid))
+			.collect();
+		assert_eq!(
+			hops,
+			vec![
+				(1, "uosmo", 10_000, 4_940, 20),
+				(2, "uatom", 4_940, 19_566, 0),
+				(3, "uusdc", 19_566, 29_064, 0),
+			]
+		);
+		assert_eq!(evaluation.amount_out, 29_064);
+		assert_eq!(evaluation.net_profit, 19_064);
+		assert!(evaluation.is_profitable());
+
+		// the other way round loses
+		let mut reversed = triangle();
+		reversed.steps = vec![step(3, osmo(), usdc()), step(2, usdc(), atom()), step(1, atom(), osmo())];
+		let evaluation = reversed.evaluate(10_000, &pools()).unwrap();
+		assert!(evaluation.net_profit < 0);
+		assert!(!evaluation.is_profitable());
+	}
+
+	#[test]
+	fn rejects_routes_it_cannot_evaluate() {
+		let pools = pools();
+
+		let mut open = triangle();
+		open.steps.pop();
+		open.to = usdc();
+		assert_eq!(open.validate(), Ok(()));
+		assert_eq!(open.evaluate(10_000, &pools), Err(FeanorError::InvalidRoute));
+
+		let mut unresolved = triangle();
+		unresolved.steps[1].pool_id = "N/A".into();
+		assert_eq!(unresolved.evaluate(10_000, &pools), Err(FeanorError::InvalidRoute));
+
+		let mut missing = triangle();
+		missing.steps[1].pool_id = "9".into();
+		assert_eq!(missing.evaluate(10_000, &pools), Err(FeanorError::PoolNotFound));
+
+		// pool 1 does not trade uusdc
+		let mut wrong_pool = triangle();
+		wrong_pool.steps[1].pool_id = "1".into();
+		assert_eq!(wrong_pool.evaluate(10_000, &pools), Err(FeanorError::InvalidPool));
+	}
+}
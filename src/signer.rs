@@ -0,0 +1,264 @@
+use bip39::{Language, Mnemonic, Seed};
+use hmac::{Hmac, Mac};
+use libsecp256k1::{Message as SecpMessage, PublicKey, SecretKey};
+use ripemd::Ripemd160;
+use sha2::{Digest, Sha256, Sha512};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::{
+	denom::Addr,
+	error::FeanorError,
+	proto::Message,
+	tx::{SignDoc, TxRaw},
+};
+
+// secp256k1 key of the unattended bot
+//
+// derives the Cosmos account key from a BIP39 mnemonic along a BIP44 path the
+// way Keplr does, or takes a raw private key, and signs SIGN_MODE_DIRECT sign
+// docs. private material lives in zeroizing buffers; libsecp256k1 keys are
+// `Copy` and never cleared, so one is only parsed from those bytes for the
+// operation that needs it and overwritten right after
+
+/// default BIP44 path of Cosmos chains, coin type 118
+pub const COSMOS_HD_PATH: &str = "m/44'/118'/0'/0/0";
+pub const OSMOSIS_PREFIX: &str = "osmo";
+
+/// offset of hardened BIP32 indexes
+const HARDENED: u32 = 0x8000_0000;
+
+type HmacSha512 = Hmac<Sha512>;
+/// key or chain code bytes, cleared on drop
+type SecretBytes = Zeroizing<[u8; 32]>;
+
+pub struct Signer {
+	secret_key: SecretBytes,
+	public_key: [u8; 33],
+	address: Addr,
+}
+
+impl Signer {
+	/// key at the Cosmos path of `phrase`, with an empty BIP39 passphrase
+	pub fn from_mnemonic(phrase: &str) -> Result<Self, FeanorError> {
+		Self::from_mnemonic_path(phrase, "", COSMOS_HD_PATH, OSMOSIS_PREFIX)
+	}
+
+	pub fn from_mnemonic_path(phrase: &str, passphrase: &str, path: &str, prefix: &str) -> Result<Self, FeanorError> {
+		let mnemonic = Mnemonic::from_phrase(phrase, Language::English).map_err(|_| FeanorError::InvalidMnemonic)?;
+		let seed = Seed::new(&mnemonic, passphrase);
+		let secret = derive_path(seed.as_bytes(), &parse_path(path)?)?;
+		Self::from_secret_key(secret, prefix)
+	}
+
+	/// 32 bytes secp256k1 private key
+	pub fn from_raw_key(key: &[u8], prefix: &str) -> Result<Self, FeanorError> {
+		let bytes: SecretBytes = Zeroizing::new(key.try_into().map_err(|_| FeanorError::InvalidKey)?);
+		Self::from_secret_key(bytes, prefix)
+	}
+
+	fn from_secret_key(secret_key: SecretBytes, prefix: &str) -> Result<Self, FeanorError> {
+		let public_key = with_secret_key(&secret_key, |key| Ok(PublicKey::from_secret_key(key).serialize_compressed()))?;
+		let address = address(&public_key, prefix)?;
+		Ok(Signer {
+			secret_key,
+			public_key,
+			address,
+		})
+	}
+
+	/// bech32 account address, e.g. `osmo1...`
+	pub fn address(&self) -> &Addr {
+		&self.address
+	}
+
+	/// compressed public key, as put in the `SignerInfo`
+	pub fn public_key(&self) -> &[u8; 33] {
+		&self.public_key
+	}
+
+	/// 64 bytes `r || s` signature of the sha256 of `sign_doc`, with a low `s`
+	pub fn sign(&self, sign_doc: &SignDoc) -> [u8; 64] {
+		self.sign_bytes(&sign_doc.encode_to_vec())
+	}
+
+	pub fn sign_bytes(&self, bytes: &[u8]) -> [u8; 64] {
+		let digest: [u8; 32] = Sha256::digest(bytes).into();
+		with_secret_key(&self.secret_key, |key| {
+			let (signature, _) = libsecp256k1::sign(&SecpMessage::parse(&digest), key);
+			Ok(signature.serialize())
+		})
+		.expect("key validated on construction")
+	}
+
+	/// sign `sign_doc` and assemble the transaction to broadcast
+	pub fn sign_tx(&self, sign_doc: SignDoc) -> TxRaw {
+		let signature = self.sign(&sign_doc).to_vec();
+		sign_doc.into_tx(signature)
+	}
+}
+
+// never print the key
+impl std::fmt::Debug for Signer {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Signer").field("address", &self.address).finish_non_exhaustive()
+	}
+}
+
+/// bech32 of `ripemd160(sha256(public_key))`
+pub fn address(public_key: &[u8; 33], prefix: &str) -> Result<Addr, FeanorError> {
+	Addr::from_bytes(prefix, &Ripemd160::digest(Sha256::digest(public_key)))
+}
+
+/// indexes of a `m/44'/118'/0'/0/0` style path, hardened ones offset by 2^31
+fn parse_path(path: &str) -> Result<Vec<u32>, FeanorError> {
+	let mut parts = path.split('/');
+	if parts.next() != Some("m") {
+		return Err(FeanorError::InvalidDerivationPath);
+	}
+	parts
+		.map(|part| {
+			let (index, hardened) = match part.strip_suffix('\'') {
+				Some(index) => (index, true),
+				None => (part, false),
+			};
+			let index: u32 = index.parse().map_err(|_| FeanorError::InvalidDerivationPath)?;
+			match (index < HARDENED, hardened) {
+				(true, true) => Ok(index | HARDENED),
+				(true, false) => Ok(index),
+				(false, _) => Err(FeanorError::InvalidDerivationPath),
+			}
+		})
+		.collect()
+}
+
+/// BIP32 private key derivation from the master key of `seed`
+fn derive_path(seed: &[u8], path: &[u32]) -> Result<SecretBytes, FeanorError> {
+	let (mut key, mut chain_code) = hmac_split(b"Bitcoin seed", &[seed])?;
+
+	for &index in path {
+		let index_bytes = index.to_be_bytes();
+		let (tweak, child_chain_code) = if index >= HARDENED {
+			hmac_split(&chain_code[..], &[&[0u8], &key[..], &index_bytes])?
+		} else {
+			let public_key = with_secret_key(&key, |key| Ok(PublicKey::from_secret_key(key).serialize_compressed()))?;
+			hmac_split(&chain_code[..], &[&public_key, &index_bytes])?
+		};
+		let child = with_secret_key(&key, |key| {
+			with_secret_key(&tweak, |tweak| key.tweak_add_assign(tweak).map_err(|_| FeanorError::InvalidKey))?;
+			Ok(Zeroizing::new(key.serialize()))
+		})?;
+		key = child;
+		chain_code = child_chain_code;
+	}
+	Ok(key)
+}
+
+/// run `f` on the key parsed from `bytes`, overwriting it afterwards
+fn with_secret_key<T>(
+	bytes: &[u8; 32],
+	f: impl FnOnce(&mut SecretKey) -> Result<T, FeanorError>,
+	) -> Result<T, FeanorError> {
+	let mut key = SecretKey::parse(bytes).map_err(|_| FeanorError::InvalidKey)?;
+	let result = f(&mut key);
+	// volatile, so the write to a dead value is not optimized out
+	unsafe { std::ptr::write_volatile(&mut key, SecretKey::default()) };
+	result
+}
+
+/// HMAC-SHA512 of `data` split into the key of its left half and the chain code of its right half
+fn hmac_split(hmac_key: &[u8], data: &[&[u8]]) -> Result<(SecretBytes, SecretBytes), FeanorError> {
+	let mut mac = HmacSha512::new_from_slice(hmac_key).map_err(|_| FeanorError::InvalidKey)?;
+	for part in data {
+		mac.update(part);
+	}
+	let mut output = mac.finalize().into_bytes();
+
+	let mut left = Zeroizing::new([0u8; 32]);
+	let mut right = Zeroizing::new([0u8; 32]);
+	left.copy_from_slice(&output[..32]);
+	right.copy_from_slice(&output[32..]);
+	output.as_mut_slice().zeroize();
+	// a left half above the curve order is the negligible invalid child case
+	with_secret_key(&left, |_| Ok(()))?;
+	Ok((left, right))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// BIP39 test mnemonic, the addresses being those Keplr and cosmjs derive
+	const MNEMONIC: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+	fn hex(value: &str) -> Vec<u8> {
+		(0..value.len())
+			.step_by(2)
+			.map(|i| u8::from_str_radix(&value[i..i + 2], 16).unwrap())
+			.collect()
+	}
+
+	#[test]
+	fn derives_the_cosmos_account_of_a_mnemonic() {
+		let signer = Signer::from_mnemonic(MNEMONIC).unwrap();
+		assert_eq!(signer.address().as_str(), "osmo19rl4cm2hmr8afy4kldpxz3fka4jguq0a5m7df8");
+		assert_eq!(
+			signer.public_key().to_vec(),
+			hex("024f4e2ad99c34d60b9ba6283c9431a8418af8673212961f97a77b6377fcd05b62")
+		);
+
+		let cosmos = Signer::from_mnemonic_path(MNEMONIC, "", COSMOS_HD_PATH, "cosmos").unwrap();
+		assert_eq!(cosmos.address().as_str(), "cosmos19rl4cm2hmr8afy4kldpxz3fka4jguq0auqdal4");
+
+		let raw = hex("c4a48e2fce1481cd3294b4490f6678090ea98d3d0e5cd984558ab0968741b104");
+		let from_key = Signer::from_raw_key(&raw, OSMOSIS_PREFIX).unwrap();
+		assert_eq!(from_key.address(), signer.address());
+	}
+
+	#[test]
+	fn follows_the_path_and_passphrase() {
+		let second = Signer::from_mnemonic_path(MNEMONIC, "", "m/44'/118'/0'/0/1", OSMOSIS_PREFIX).unwrap();
+		assert_eq!(second.address().as_str(), "osmo1jrkmdcwgq94uaamx6zax2luewlhf7u4k5r4pqs");
+		let protected = Signer::from_mnemonic_path(MNEMONIC, "TREZOR", COSMOS_HD_PATH, OSMOSIS_PREFIX).unwrap();
+		assert_eq!(protected.address().as_str(), "osmo12fdxecq3dp28aaswp2n3yk35p782g3w99ez6dg");
+	}
+
+	#[test]
+	fn bip32_master_and_hardened_child() {
+		// BIP32 test vector 1
+		let seed = hex("000102030405060708090a0b0c0d0e0f");
+		let master = derive_path(&seed, &[]).unwrap();
+		assert_eq!(
+			master.to_vec(),
+			hex("e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35")
+		);
+		let child = derive_path(&seed, &parse_path("m/0'").unwrap()).unwrap();
+		assert_eq!(
+			child.to_vec(),
+			hex("edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea")
+		);
+	}
+
+	#[test]
+	fn rejects_bad_input() {
+		assert_eq!(parse_path("m/44'/118'/0'/0/0"), Ok(vec![44 | HARDENED, 118 | HARDENED, HARDENED, 0, 0]));
+		assert_eq!(parse_path("44'/118'"), Err(FeanorError::InvalidDerivationPath));
+		assert_eq!(parse_path("m/2147483648"), Err(FeanorError::InvalidDerivationPath));
+		assert_eq!(parse_path("m/x"), Err(FeanorError::InvalidDerivationPath));
+		let wrong_checksum = MNEMONIC.replace("about", "abandon");
+		assert_eq!(Signer::from_mnemonic(&wrong_checksum).unwrap_err(), FeanorError::InvalidMnemonic);
+		assert_eq!(Signer::from_raw_key(&[1; 31], OSMOSIS_PREFIX).unwrap_err(), FeanorError::InvalidKey);
+		assert_eq!(Signer::from_raw_key(&[0; 32], OSMOSIS_PREFIX).unwrap_err(), FeanorError::InvalidKey);
+	}
+
+	#[test]
+	fn signatures_verify_against_the_public_key() {
+		let signer = Signer::from_mnemonic(MNEMONIC).unwrap();
+		let signature = signer.sign_bytes(b"sign doc");
+		let digest: [u8; 32] = Sha256::digest(b"sign doc").into();
+		let public_key = PublicKey::parse_compressed(signer.public_key()).unwrap();
+		let parsed = libsecp256k1::Signature::parse_standard(&signature).unwrap();
+		assert!(libsecp256k1::verify(&SecpMessage::parse(&digest), &parsed, &public_key));
+		// RFC 6979 nonces make signing deterministic
+		assert_eq!(signer.sign_bytes(b"sign doc"), signature);
+	}
+}
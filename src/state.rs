@@ -0,0 +1,237 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+	account_info::AccountInfo,
+	program_error::ProgramError,
+	pubkey::Pubkey,
+};
+
+use crate::error::FeanorError;
+
+/// prefix of the seeds every vault PDA is derived from
+pub const VAULT_SEED: &[u8] = b"varda_vault";
+
+/// seed of the single gate config PDA
+pub const GATE_SEED: &[u8] = b"varda_gate";
+
+// settings the owner chooses for its vault
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct VaultConfig {
+	/// minimum profit a route must return, in basis points of the loan
+	pub min_profit_bps: u16,
+	/// hash of the route the vault is allowed to execute, zero when none
+	pub route_hash: [u8; 32],
+}
+
+impl VaultConfig {
+	pub const LEN: usize = 2 + 32;
+}
+
+// the Varda Vault, one per NFT holder, owned by the feanor program
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Vault {
+	pub is_initialized: bool,
+	/// wallet that created the vault and may withdraw from it
+	pub owner: Pubkey,
+	/// Varda NFT mint the vault was opened with
+	pub nft_mint: Pubkey,
+	/// lamports deposited by the owner and not yet withdrawn
+	pub treasury: u64,
+	/// lamports of profit settled by executed routes
+	pub profit: u64,
+	pub config: VaultConfig,
+	/// bump of the vault PDA
+	pub bump: u8,
+}
+
+impl Vault {
+	pub const LEN: usize = 1 + 32 + 32 + 8 + 8 + VaultConfig::LEN + 1;
+
+	/// seeds of the vault PDA of `owner`, without the bump
+	pub fn seeds(owner: &Pubkey) -> [&[u8]; 2] {
+		[VAULT_SEED, owner.as_ref()]
+	}
+
+	/// address and bump of the vault PDA of `owner`
+	pub fn find_address(program_id: &Pubkey, owner: &Pubkey) -> (Pubkey, u8) {
+		Pubkey::find_program_address(&Self::seeds(owner), program_id)
+	}
+
+	/// read an initialized vault, checking it is owned by the program
+	pub fn load(account: &AccountInfo, program_id: &Pubkey) -> Result<Self, ProgramError> {
+		if account.owner != program_id {
+			return Err(ProgramError::IncorrectProgramId);
+		}
+		let vault = Self::try_from_slice(&account.data.borrow())
+			.map_err(|_| ProgramError::InvalidAccountData)?;
+		if !vault.is_initialized {
+			return Err(ProgramError::UninitializedAccount);
+		}
+		Ok(vault)
+	}
+
+	/// write the vault back into its account
+	pub fn save(&self, account: &AccountInfo) -> Result<(), ProgramError> {
+		let mut data = account.data.borrow_mut();
+		self.serialize(&mut &mut data[..])
+			.map_err(|_| ProgramError::AccountDataTooSmall)
+	}
+
+	/// check that `account` is the PDA of this vault
+	pub fn check_address(&self, account: &AccountInfo, program_id: &Pubkey) -> Result<(), ProgramError> {
+		let expected = Pubkey::create_program_address(
+			&[VAULT_SEED, self.owner.as_ref(), &[self.bump]],
+			program_id,
+		)
+		.map_err(|_| FeanorError::InvalidVaultAddress)?;
+		if expected != *account.key {
+			return Err(FeanorError::InvalidVaultAddress.into());
+		}
+		Ok(())
+	}
+}
+
+// program wide settings of the Varda NFT ownership gate
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct GateConfig {
+	pub is_initialized: bool,
+	/// wallet allowed to change the gate, the program upgrade authority at creation
+	pub authority: Pubkey,
+	/// Varda NFT mint, or the collection mint when `verify_collection` is set
+	pub collection_mint: Pubkey,
+	/// check the Metaplex metadata of the held NFT for a verified collection
+	pub verify_collection: bool,
+	/// bump of the gate config PDA
+	pub bump: u8,
+}
+
+impl GateConfig {
+	pub const LEN: usize = 1 + 32 + 32 + 1 + 1;
+
+	/// address and bump of the gate config PDA
+	pub fn find_address(program_id: &Pubkey) -> (Pubkey, u8) {
+		Pubkey::find_program_address(&[GATE_SEED], program_id)
+	}
+
+	/// read the initialized gate config, checking its address and owner
+	pub fn load(account: &AccountInfo, program_id: &Pubkey) -> Result<Self, ProgramError> {
+		if account.owner != program_id {
+			return Err(FeanorError::GateNotConfigured.into());
+		}
+		let config = Self::try_from_slice(&account.data.borrow())
+			.map_err(|_| ProgramError::InvalidAccountData)?;
+		if !config.is_initialized {
+			return Err(FeanorError::GateNotConfigured.into());
+		}
+		let expected = Pubkey::create_program_address(&[GATE_SEED, &[config.bump]], program_id)
+			.map_err(|_| FeanorError::GateNotConfigured)?;
+		if expected != *account.key {
+			return Err(FeanorError::GateNotConfigured.into());
+		}
+		Ok(config)
+	}
+
+	/// write the config back into its account
+	pub fn save(&self, account: &AccountInfo) -> Result<(), ProgramError> {
+		let mut data = account.data.borrow_mut();
+		self.serialize(&mut &mut data[..])
+			.map_err(|_| ProgramError::AccountDataTooSmall)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn vault(owner: Pubkey, bump: u8) -> Vault {
+		Vault {
+			is_initialized: true,
+			owner,
+			nft_mint: Pubkey::new_from_array([2; 32]),
+			treasury: 5_000,
+			profit: 42,
+			config: VaultConfig {
+				min_profit_bps: 30,
+				route_hash: [4; 32],
+			},
+			bump,
+		}
+	}
+
+	#[test]
+	fn serializes_vaults_in_len_bytes() {
+		let vault = vault(Pubkey::new_from_array([1; 32]), 254);
+		let bytes = borsh::to_vec(&vault).unwrap();
+		assert_eq!(bytes.len(), Vault::LEN);
+		assert_eq!(Vault::try_from_slice(&bytes).unwrap(), vault);
+		assert_eq!(borsh::to_vec(&Vault::default()).unwrap().len(), Vault::LEN);
+		assert_eq!(borsh::to_vec(&GateConfig::default()).unwrap().len(), GateConfig::LEN);
+	}
+
+	#[test]
+	fn saves_and_loads_through_the_account() {
+		let program_id = Pubkey::new_unique();
+		let owner = Pubkey::new_unique();
+		let (key, bump) = Vault::find_address(&program_id, &owner);
+		let vault = vault(owner, bump);
+		let (mut lamports, mut data) = (0, vec![0; Vault::LEN]);
+		let account = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &program_id, false, 0);
+
+		assert_eq!(Vault::load(&account, &program_id), Err(ProgramError::UninitializedAccount));
+		vault.save(&account).unwrap();
+		assert_eq!(Vault::load(&account, &program_id).unwrap(), vault);
+		assert_eq!(Vault::load(&account, &owner), Err(ProgramError::IncorrectProgramId));
+
+		let (mut lamports, mut data) = (0, vec![0; Vault::LEN - 1]);
+		let short = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &program_id, false, 0);
+		assert_eq!(vault.save(&short), Err(ProgramError::AccountDataTooSmall));
+	}
+
+	#[test]
+	fn derives_one_vault_address_per_owner() {
+		let program_id = Pubkey::new_unique();
+		let owner = Pubkey::new_unique();
+		let (key, bump) = Vault::find_address(&program_id, &owner);
+		assert_eq!(Pubkey::create_program_address(&[VAULT_SEED, owner.as_ref(), &[bump]], &program_id), Ok(key));
+		assert!(!key.is_on_curve());
+		assert_ne!(Vault::find_address(&program_id, &Pubkey::new_unique()).0, key);
+		assert_ne!(Vault::find_address(&Pubkey::new_unique(), &owner).0, key);
+
+		let (mut lamports, mut data) = (0, vec![]);
+		let account = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &program_id, false, 0);
+		assert_eq!(vault(owner, bump).check_address(&account, &program_id), Ok(()));
+		assert_eq!(
+			vault(Pubkey::new_unique(), bump).check_address(&account, &program_id),
+			Err(FeanorError::InvalidVaultAddress.into())
+		);
+		let other = Pubkey::new_unique();
+		let account = AccountInfo::new(&other, false, true, &mut lamports, &mut data, &program_id, false, 0);
+		assert_eq!(
+			vault(owner, bump).check_address(&account, &program_id),
+			Err(FeanorError::InvalidVaultAddress.into())
+		);
+	}
+
+	#[test]
+	fn loads_the_gate_config_only_at_its_pda() {
+		let program_id = Pubkey::new_unique();
+		let (key, bump) = GateConfig::find_address(&program_id);
+		let config = GateConfig {
+			is_initialized: true,
+			authority: Pubkey::new_unique(),
+			collection_mint: Pubkey::new_unique(),
+			verify_collection: true,
+			bump,
+		};
+		let (mut lamports, mut data) = (0, borsh::to_vec(&config).unwrap());
+		let account = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &program_id, false, 0);
+		assert_eq!(GateConfig::load(&account, &program_id).unwrap(), config);
+
+		let elsewhere = Pubkey::new_unique();
+		let (mut lamports, mut data) = (0, borsh::to_vec(&config).unwrap());
+		let account = AccountInfo::new(&elsewhere, false, false, &mut lamports, &mut data, &program_id, false, 0);
+		assert_eq!(GateConfig::load(&account, &program_id), Err(FeanorError::GateNotConfigured.into()));
+	}
+}